@@ -0,0 +1,683 @@
+use std::collections::HashMap;
+use std::fmt;
+
+// Assembler for a subset of Octo: labels, :alias, :const, :macro, :org,
+// :byte, if ... then / begin ... else ... end, loop ... while ... again,
+// the Octo statement syntax and raw bytes for sprite data.
+// As in Octo, execution starts at the label `main`.
+
+// Programs are loaded at 0x200
+const ORIGIN: usize = 0x200;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AsmError {
+    pub line: usize, // 1-based source line
+    pub message: String
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for AsmError {}
+
+// Assemble Octo source into a ROM to be loaded at 0x200
+pub fn assemble(source: &str) -> Result<Vec<u8>, AsmError> {
+    let mut asm = Assembler::new(tokenize(source));
+
+    // Reserve a slot for the jump to main
+    asm.emit_op(0x1000, 1)?;
+
+    while asm.pos < asm.tokens.len() {
+        asm.statement()?;
+    }
+
+    if let Some(block) = asm.blocks.last() {
+        let what = match block {
+            Block::If { .. } | Block::Else { .. } => "'if ... begin' without 'end'",
+            Block::Loop { .. } => "'loop' without 'again'"
+        };
+        return Err(AsmError { line: block.line(), message: what.to_string() });
+    }
+
+    let main = match asm.labels.get("main") {
+        Some(&main) => main,
+        None => return Err(AsmError { line: 1, message: "missing 'main' label".to_string() })
+    };
+    asm.patch_nnn(ORIGIN, main);
+
+    for fixup in std::mem::take(&mut asm.fixups) {
+        let target = match asm.labels.get(&fixup.name) {
+            Some(&target) => target,
+            None => return Err(AsmError { line: fixup.line, message: format!("undefined name '{}'", fixup.name) })
+        };
+        match fixup.kind {
+            FixupKind::Nnn => {
+                if target > 0xFFF {
+                    return Err(AsmError { line: fixup.line, message: format!("address of '{}' does not fit in 12 bits", fixup.name) });
+                }
+                asm.patch_nnn(fixup.address, target);
+            }
+            FixupKind::Long => asm.patch_word(fixup.address, target)
+        }
+    }
+
+    Ok(asm.rom)
+}
+
+#[derive(Clone)]
+struct Token {
+    text: String,
+    line: usize
+}
+
+// Split on whitespace, dropping # comments
+fn tokenize(source: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    for (i, line) in source.lines().enumerate() {
+        let code = match line.find('#') {
+            Some(comment) => &line[..comment],
+            None => line
+        };
+        for word in code.split_whitespace() {
+            tokens.push(Token { text: word.to_string(), line: i + 1 });
+        }
+    }
+    tokens
+}
+
+struct Macro {
+    params: Vec<String>,
+    body: Vec<Token>
+}
+
+// Open control flow structures
+enum Block {
+    If { jump: usize, line: usize }, // jump over the body when the condition fails
+    Else { jump: usize, line: usize }, // jump over the else branch
+    Loop { start: u16, breaks: Vec<usize>, line: usize }
+}
+
+impl Block {
+    fn line(&self) -> usize {
+        match self {
+            Block::If { line, .. } | Block::Else { line, .. } | Block::Loop { line, .. } => *line
+        }
+    }
+}
+
+enum FixupKind {
+    Nnn, // low 12 bits of the opcode at the address
+    Long // 16 bit word at the address
+}
+
+// Forward reference to a label, patched once all labels are known
+struct Fixup {
+    address: usize,
+    name: String,
+    kind: FixupKind,
+    line: usize
+}
+
+// A condition of if / while: the instruction that skips when it holds,
+// and the one that skips when it does not
+struct Condition {
+    skip_if_true: u16,
+    skip_if_false: u16
+}
+
+struct Assembler {
+    tokens: Vec<Token>,
+    pos: usize,
+    rom: Vec<u8>,
+    here: usize, // address of the next emitted byte
+    labels: HashMap<String, u16>,
+    constants: HashMap<String, i64>,
+    aliases: HashMap<String, u8>,
+    macros: HashMap<String, Macro>,
+    fixups: Vec<Fixup>,
+    blocks: Vec<Block>,
+    expansions: usize // guards against recursive macros
+}
+
+impl Assembler {
+    fn new(tokens: Vec<Token>) -> Assembler {
+        Assembler {
+            tokens,
+            pos: 0,
+            rom: Vec::new(),
+            here: ORIGIN,
+            labels: HashMap::new(),
+            constants: HashMap::new(),
+            aliases: HashMap::new(),
+            macros: HashMap::new(),
+            fixups: Vec::new(),
+            blocks: Vec::new(),
+            expansions: 0
+        }
+    }
+
+    fn error<T>(&self, line: usize, message: String) -> Result<T, AsmError> {
+        Err(AsmError { line, message })
+    }
+
+    // Line of the last consumed token, for errors
+    fn line(&self) -> usize {
+        match self.tokens.get(self.pos.saturating_sub(1)) {
+            Some(token) => token.line,
+            None => self.tokens.last().map_or(1, |t| t.line)
+        }
+    }
+
+    fn next(&mut self) -> Result<Token, AsmError> {
+        match self.tokens.get(self.pos) {
+            Some(token) => {
+                self.pos += 1;
+                Ok(token.clone())
+            }
+            None => self.error(self.line(), "unexpected end of file".to_string())
+        }
+    }
+
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.pos).map(|t| t.text.as_str())
+    }
+
+    fn expect(&mut self, text: &str) -> Result<(), AsmError> {
+        let token = self.next()?;
+        if token.text != text {
+            return self.error(token.line, format!("expected '{}', found '{}'", text, token.text));
+        }
+        Ok(())
+    }
+
+    fn emit_byte(&mut self, byte: u8, line: usize) -> Result<(), AsmError> {
+        let offset = self.here - ORIGIN;
+        if self.here > 0xFFFF {
+            return self.error(line, "program does not fit in memory".to_string());
+        }
+        if offset >= self.rom.len() {
+            self.rom.resize(offset + 1, 0);
+        }
+        self.rom[offset] = byte;
+        self.here += 1;
+        Ok(())
+    }
+
+    fn emit_op(&mut self, opcode: u16, line: usize) -> Result<(), AsmError> {
+        self.emit_byte((opcode >> 8) as u8, line)?;
+        self.emit_byte(opcode as u8, line)
+    }
+
+    fn patch_nnn(&mut self, address: usize, target: u16) {
+        let offset = address - ORIGIN;
+        self.rom[offset] = (self.rom[offset] & 0xF0) | ((target >> 8) as u8 & 0x0F);
+        self.rom[offset + 1] = target as u8;
+    }
+
+    // Point an if / loop jump at target, which like any NNN has to be below 0x1000
+    fn patch_jump(&mut self, address: usize, target: usize, line: usize) -> Result<(), AsmError> {
+        if target > 0xFFF {
+            return self.error(line, format!("jump target {:#05X} out of range", target));
+        }
+        self.patch_nnn(address, target as u16);
+        Ok(())
+    }
+
+    fn patch_word(&mut self, address: usize, target: u16) {
+        let offset = address - ORIGIN;
+        self.rom[offset] = (target >> 8) as u8;
+        self.rom[offset + 1] = target as u8;
+    }
+
+    fn define(&mut self, name: &str, line: usize) -> Result<(), AsmError> {
+        if self.labels.contains_key(name) || self.constants.contains_key(name) {
+            return self.error(line, format!("'{}' is already defined", name));
+        }
+        if parse_register(name).is_some() || parse_number(name).is_some() {
+            return self.error(line, format!("'{}' is not a valid name", name));
+        }
+        Ok(())
+    }
+
+    fn register(&mut self) -> Result<u8, AsmError> {
+        let token = self.next()?;
+        match self.lookup_register(&token.text) {
+            Some(r) => Ok(r),
+            None => self.error(token.line, format!("expected a register, found '{}'", token.text))
+        }
+    }
+
+    fn lookup_register(&self, text: &str) -> Option<u8> {
+        parse_register(text).or_else(|| self.aliases.get(text).copied())
+    }
+
+    // A number or constant, checked against an inclusive range
+    fn value(&mut self, min: i64, max: i64) -> Result<i64, AsmError> {
+        let token = self.next()?;
+        let value = match self.lookup_value(&token.text) {
+            Some(value) => value,
+            None => return self.error(token.line, format!("undefined name '{}'", token.text))
+        };
+        if value < min || value > max {
+            return self.error(token.line, format!("value {} out of range {} to {}", token.text, min, max));
+        }
+        Ok(value)
+    }
+
+    fn lookup_value(&self, text: &str) -> Option<i64> {
+        parse_number(text)
+            .or_else(|| self.constants.get(text).copied())
+            .or_else(|| self.labels.get(text).map(|&a| a as i64))
+    }
+
+    fn byte(&mut self) -> Result<u8, AsmError> {
+        Ok(self.value(-128, 255)? as u8)
+    }
+
+    fn nibble(&mut self) -> Result<u8, AsmError> {
+        Ok(self.value(0, 15)? as u8)
+    }
+
+    // Emit an instruction with a 12 bit address, which may be a forward reference
+    fn emit_address_op(&mut self, opcode: u16) -> Result<(), AsmError> {
+        let token = self.next()?;
+        let address = self.here;
+        match self.lookup_value(&token.text) {
+            Some(value) if (0..=0xFFF).contains(&value) => self.emit_op(opcode | value as u16, token.line)?,
+            Some(_) => return self.error(token.line, format!("address {} out of range", token.text)),
+            None => {
+                self.emit_op(opcode, token.line)?;
+                self.fixups.push(Fixup { address, name: token.text, kind: FixupKind::Nnn, line: token.line });
+            }
+        }
+        Ok(())
+    }
+
+    fn statement(&mut self) -> Result<(), AsmError> {
+        let token = self.next()?;
+        let line = token.line;
+
+        match token.text.as_str() {
+            ":" => {
+                let name = self.next()?.text;
+                self.define(&name, line)?;
+                self.labels.insert(name, self.here as u16);
+            }
+            ":alias" => {
+                let name = self.next()?.text;
+                let r = self.register()?;
+                self.aliases.insert(name, r);
+            }
+            ":const" => {
+                let name = self.next()?.text;
+                self.define(&name, line)?;
+                let value = self.value(-0x8000, 0xFFFF)?;
+                self.constants.insert(name, value);
+            }
+            ":macro" => self.define_macro(line)?,
+            ":org" => {
+                let address = self.value(ORIGIN as i64, 0xFFFF)?;
+                self.here = address as usize;
+            }
+            ":byte" => {
+                let byte = self.byte()?;
+                self.emit_byte(byte, line)?;
+            }
+            ":call" => self.emit_address_op(0x2000)?,
+            "clear" => self.emit_op(0x00E0, line)?,
+            "return" | ";" => self.emit_op(0x00EE, line)?,
+            "exit" => self.emit_op(0x00FD, line)?,
+            "lores" => self.emit_op(0x00FE, line)?,
+            "hires" => self.emit_op(0x00FF, line)?,
+            "scroll-down" => {
+                let n = self.nibble()?;
+                self.emit_op(0x00C0 | n as u16, line)?;
+            }
+            "scroll-right" => self.emit_op(0x00FB, line)?,
+            "scroll-left" => self.emit_op(0x00FC, line)?,
+            "jump" => self.emit_address_op(0x1000)?,
+            "jump0" => self.emit_address_op(0xB000)?,
+            "bcd" => {
+                let x = self.register()?;
+                self.emit_op(xnn(0xF000, x, 0x33), line)?;
+            }
+            "save" | "load" => {
+                let x = self.register()?;
+                let store = token.text == "save";
+                if self.peek() == Some("-") {
+                    self.next()?;
+                    let y = self.register()?;
+                    self.emit_op(xy(0x5000, x, y, if store { 0x2 } else { 0x3 }), line)?;
+                } else {
+                    self.emit_op(xnn(0xF000, x, if store { 0x55 } else { 0x65 }), line)?;
+                }
+            }
+            "saveflags" => {
+                let x = self.register()?;
+                self.emit_op(xnn(0xF000, x, 0x75), line)?;
+            }
+            "loadflags" => {
+                let x = self.register()?;
+                self.emit_op(xnn(0xF000, x, 0x85), line)?;
+            }
+            "sprite" => {
+                let x = self.register()?;
+                let y = self.register()?;
+                let n = self.nibble()?;
+                self.emit_op(xy(0xD000, x, y, n as u16), line)?;
+            }
+            "plane" => {
+                let n = self.value(0, 3)? as u8;
+                self.emit_op(xnn(0xF000, n, 0x01), line)?;
+            }
+            "audio" => self.emit_op(0xF002, line)?,
+            "delay" | "buzzer" | "pitch" => {
+                self.expect(":=")?;
+                let x = self.register()?;
+                let nn = match token.text.as_str() {
+                    "delay" => 0x15,
+                    "buzzer" => 0x18,
+                    _ => 0x3A
+                };
+                self.emit_op(xnn(0xF000, x, nn), line)?;
+            }
+            "i" => self.i_statement(line)?,
+            "if" => self.if_statement(line)?,
+            "else" => match self.blocks.pop() {
+                Some(Block::If { jump, line: if_line }) => {
+                    let skip_else = self.here;
+                    self.emit_op(0x1000, line)?;
+                    self.patch_jump(jump, self.here, line)?;
+                    self.blocks.push(Block::Else { jump: skip_else, line: if_line });
+                }
+                _ => return self.error(line, "'else' without 'if ... begin'".to_string())
+            },
+            "end" => match self.blocks.pop() {
+                Some(Block::If { jump, .. }) | Some(Block::Else { jump, .. }) => self.patch_jump(jump, self.here, line)?,
+                _ => return self.error(line, "'end' without 'if ... begin'".to_string())
+            },
+            "loop" => self.blocks.push(Block::Loop { start: self.here as u16, breaks: Vec::new(), line }),
+            "while" => {
+                let condition = self.condition()?;
+                // Leave the loop when the condition fails
+                self.emit_op(condition.skip_if_true, line)?;
+                let jump = self.here;
+                self.emit_op(0x1000, line)?;
+                match self.blocks.iter_mut().rev().find(|b| matches!(b, Block::Loop { .. })) {
+                    Some(Block::Loop { breaks, .. }) => breaks.push(jump),
+                    _ => return self.error(line, "'while' outside of 'loop'".to_string())
+                }
+            }
+            "again" => match self.blocks.pop() {
+                Some(Block::Loop { start, breaks, .. }) => {
+                    let jump = self.here;
+                    self.emit_op(0x1000, line)?;
+                    self.patch_jump(jump, start as usize, line)?;
+                    for jump in breaks {
+                        self.patch_jump(jump, self.here, line)?;
+                    }
+                }
+                _ => return self.error(line, "'again' without 'loop'".to_string())
+            },
+            text => {
+                if let Some(x) = self.lookup_register(text) {
+                    return self.register_statement(x, line);
+                }
+                if self.macros.contains_key(text) {
+                    return self.expand_macro(text.to_string(), line);
+                }
+                if let Some(number) = parse_number(text).or_else(|| self.constants.get(text).copied()) {
+                    // Raw data, such as sprites
+                    if !(-128..=255).contains(&number) {
+                        return self.error(line, format!("byte {} out of range", text));
+                    }
+                    return self.emit_byte(number as u8, line);
+                }
+                if text.starts_with(':') {
+                    return self.error(line, format!("unsupported directive '{}'", text));
+                }
+
+                // Any other name is a subroutine call
+                self.pos -= 1;
+                self.emit_address_op(0x2000)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn i_statement(&mut self, line: usize) -> Result<(), AsmError> {
+        let op = self.next()?;
+        match op.text.as_str() {
+            ":=" => match self.peek() {
+                Some("hex") | Some("bighex") => {
+                    let big = self.next()?.text == "bighex";
+                    let x = self.register()?;
+                    self.emit_op(xnn(0xF000, x, if big { 0x30 } else { 0x29 }), line)?;
+                }
+                Some("long") => {
+                    self.next()?;
+                    let token = self.next()?;
+                    self.emit_op(0xF000, line)?;
+                    let address = self.here;
+                    match self.lookup_value(&token.text) {
+                        Some(value) if (0..=0xFFFF).contains(&value) => self.emit_op(value as u16, line)?,
+                        Some(_) => return self.error(token.line, format!("address {} out of range", token.text)),
+                        None => {
+                            self.emit_op(0x0000, line)?;
+                            self.fixups.push(Fixup { address, name: token.text, kind: FixupKind::Long, line: token.line });
+                        }
+                    }
+                }
+                _ => self.emit_address_op(0xA000)?
+            },
+            "+=" => {
+                let x = self.register()?;
+                self.emit_op(xnn(0xF000, x, 0x1E), line)?;
+            }
+            other => return self.error(op.line, format!("unknown operator 'i {}'", other))
+        }
+        Ok(())
+    }
+
+    // vX := ..., vX += ... and the other register operators
+    fn register_statement(&mut self, x: u8, line: usize) -> Result<(), AsmError> {
+        let op = self.next()?;
+        let rhs = match self.peek() {
+            Some(text) => text.to_string(),
+            None => return self.error(line, "unexpected end of file".to_string())
+        };
+        let y = self.lookup_register(&rhs);
+
+        let opcode = match (op.text.as_str(), y) {
+            (":=", Some(y)) => xy(0x8000, x, y, 0x0),
+            (":=", None) => match rhs.as_str() {
+                "random" => {
+                    self.next()?;
+                    let nn = self.byte()?;
+                    return self.emit_op(xnn(0xC000, x, nn), line);
+                }
+                "key" => xnn(0xF000, x, 0x0A),
+                "delay" => xnn(0xF000, x, 0x07),
+                _ => {
+                    let nn = self.byte()?;
+                    return self.emit_op(xnn(0x6000, x, nn), line);
+                }
+            },
+            ("+=", Some(y)) => xy(0x8000, x, y, 0x4),
+            ("+=", None) => {
+                let nn = self.byte()?;
+                return self.emit_op(xnn(0x7000, x, nn), line);
+            }
+            ("-=", Some(y)) => xy(0x8000, x, y, 0x5),
+            ("-=", None) => {
+                let nn = self.byte()?;
+                return self.emit_op(xnn(0x7000, x, nn.wrapping_neg()), line);
+            }
+            ("=-", Some(y)) => xy(0x8000, x, y, 0x7),
+            ("|=", Some(y)) => xy(0x8000, x, y, 0x1),
+            ("&=", Some(y)) => xy(0x8000, x, y, 0x2),
+            ("^=", Some(y)) => xy(0x8000, x, y, 0x3),
+            (">>=", Some(y)) => xy(0x8000, x, y, 0x6),
+            ("<<=", Some(y)) => xy(0x8000, x, y, 0xE),
+            (op, _) => return self.error(line, format!("unsupported operation 'v{:X} {} {}'", x, op, rhs))
+        };
+
+        self.next()?;
+        self.emit_op(opcode, line)?;
+        Ok(())
+    }
+
+    fn condition(&mut self) -> Result<Condition, AsmError> {
+        let x = self.register()?;
+        let op = self.next()?;
+
+        match op.text.as_str() {
+            "key" => return Ok(Condition { skip_if_true: xnn(0xE000, x, 0x9E), skip_if_false: xnn(0xE000, x, 0xA1) }),
+            "-key" => return Ok(Condition { skip_if_true: xnn(0xE000, x, 0xA1), skip_if_false: xnn(0xE000, x, 0x9E) }),
+            "==" | "!=" => {}
+            other => return self.error(op.line, format!("unsupported comparison '{}'", other))
+        }
+
+        let rhs = self.peek().unwrap_or("").to_string();
+        let (equal, not_equal) = match self.lookup_register(&rhs) {
+            Some(y) => {
+                self.next()?;
+                (xy(0x5000, x, y, 0x0), xy(0x9000, x, y, 0x0))
+            }
+            None => {
+                let nn = self.byte()?;
+                (xnn(0x3000, x, nn), xnn(0x4000, x, nn))
+            }
+        };
+
+        // equal skips when vX == rhs, not_equal when vX != rhs
+        if op.text == "==" {
+            Ok(Condition { skip_if_true: equal, skip_if_false: not_equal })
+        } else {
+            Ok(Condition { skip_if_true: not_equal, skip_if_false: equal })
+        }
+    }
+
+    fn if_statement(&mut self, line: usize) -> Result<(), AsmError> {
+        let condition = self.condition()?;
+        let keyword = self.next()?;
+
+        match keyword.text.as_str() {
+            "then" => {
+                self.emit_op(condition.skip_if_false, line)?;
+                if self.pos >= self.tokens.len() {
+                    return self.error(line, "expected a statement after 'then'".to_string());
+                }
+                self.statement()
+            }
+            "begin" => {
+                // Skip the jump past the body when the condition holds
+                self.emit_op(condition.skip_if_true, line)?;
+                let jump = self.here;
+                self.emit_op(0x1000, line)?;
+                self.blocks.push(Block::If { jump, line });
+                Ok(())
+            }
+            other => self.error(keyword.line, format!("expected 'then' or 'begin', found '{}'", other))
+        }
+    }
+
+    fn define_macro(&mut self, line: usize) -> Result<(), AsmError> {
+        let name = self.next()?.text;
+        let mut params = Vec::new();
+        loop {
+            let token = self.next()?;
+            if token.text == "{" {
+                break;
+            }
+            params.push(token.text);
+        }
+
+        let mut body = Vec::new();
+        let mut depth = 1;
+        loop {
+            let token = self.next()?;
+            match token.text.as_str() {
+                "{" => depth += 1,
+                "}" => {
+                    depth -= 1;
+                    if depth == 0 {
+                        break;
+                    }
+                }
+                _ => {}
+            }
+            body.push(token);
+        }
+
+        if self.macros.contains_key(&name) {
+            return self.error(line, format!("macro '{}' is already defined", name));
+        }
+        self.macros.insert(name, Macro { params, body });
+        Ok(())
+    }
+
+    // Replace the invocation with the macro body, arguments substituted
+    fn expand_macro(&mut self, name: String, line: usize) -> Result<(), AsmError> {
+        self.expansions += 1;
+        if self.expansions > 10_000 {
+            return self.error(line, format!("macro '{}' expands recursively", name));
+        }
+
+        let params = self.macros[&name].params.clone();
+        let mut args = HashMap::new();
+        for param in params {
+            let arg = self.next()?;
+            args.insert(param, arg.text);
+        }
+
+        let body: Vec<Token> = self.macros[&name]
+            .body
+            .iter()
+            .map(|t| Token { text: args.get(&t.text).cloned().unwrap_or(t.text.clone()), line: t.line })
+            .collect();
+        self.tokens.splice(self.pos..self.pos, body);
+        Ok(())
+    }
+}
+
+fn xy(op: u16, x: u8, y: u8, n: u16) -> u16 {
+    op | (x as u16) << 8 | (y as u16) << 4 | n
+}
+
+fn xnn(op: u16, x: u8, nn: u8) -> u16 {
+    op | (x as u16) << 8 | nn as u16
+}
+
+// v0 - vF, either case
+fn parse_register(text: &str) -> Option<u8> {
+    let digit = text.strip_prefix('v').or_else(|| text.strip_prefix('V'))?;
+    if digit.len() != 1 {
+        return None;
+    }
+    u8::from_str_radix(digit, 16).ok()
+}
+
+// Decimal, 0x hex or 0b binary, optionally negative
+fn parse_number(text: &str) -> Option<i64> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, text)
+    };
+
+    let value = if let Some(hex) = digits.strip_prefix("0x").or_else(|| digits.strip_prefix("0X")) {
+        i64::from_str_radix(hex, 16).ok()?
+    } else if let Some(bin) = digits.strip_prefix("0b").or_else(|| digits.strip_prefix("0B")) {
+        i64::from_str_radix(bin, 2).ok()?
+    } else if !digits.is_empty() && digits.chars().all(|c| c.is_ascii_digit()) {
+        digits.parse().ok()?
+    } else {
+        return None;
+    };
+
+    Some(if negative { -value } else { value })
+}
@@ -0,0 +1,44 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+
+use chip8::assembler::assemble;
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let (source_path, out_path) = match args.len() {
+        2 => (PathBuf::from(&args[1]), PathBuf::from(&args[1]).with_extension("ch8")),
+        4 if args[2] == "-o" => (PathBuf::from(&args[1]), PathBuf::from(&args[3])),
+        _ => {
+            eprintln!("Usage: chip8-asm <source.8o> [-o <out.ch8>]");
+            process::exit(2);
+        }
+    };
+
+    let source = match fs::read_to_string(&source_path) {
+        Ok(source) => source,
+        Err(e) => {
+            eprintln!("Error reading {}: {e}", source_path.display());
+            process::exit(1);
+        }
+    };
+
+    let rom = match assemble(&source) {
+        Ok(rom) => rom,
+        Err(e) => {
+            // Point at the offending source line
+            eprintln!("{}:{}: {}", source_path.display(), e.line, e.message);
+            if let Some(text) = source.lines().nth(e.line - 1) {
+                eprintln!("    {}", text.trim());
+            }
+            process::exit(1);
+        }
+    };
+
+    if let Err(e) = fs::write(&out_path, &rom) {
+        eprintln!("Error writing {}: {e}", out_path.display());
+        process::exit(1);
+    }
+    println!("{}: {} bytes", out_path.display(), rom.len());
+}
@@ -0,0 +1,34 @@
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process;
+
+use chip8::disasm::disassemble;
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() != 2 {
+        eprintln!("Usage: chip8-disasm <rom>");
+        process::exit(2);
+    }
+
+    // Accept a path, or a file name under binaries/ like the emulator
+    let fname = &args[1];
+    let path = if Path::new(fname).exists() {
+        Path::new(fname).to_path_buf()
+    } else {
+        Path::new("binaries").join(fname)
+    };
+
+    let rom = match fs::read(&path) {
+        Ok(rom) => rom,
+        Err(e) => {
+            eprintln!("Error reading {}: {e}", path.display());
+            process::exit(1);
+        }
+    };
+
+    for line in disassemble(&rom) {
+        println!("{line}");
+    }
+}
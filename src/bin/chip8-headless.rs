@@ -0,0 +1,212 @@
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::ops::RangeInclusive;
+use std::path::Path;
+use std::process;
+
+use chip8::headless::{display_ascii, display_pbm, display_png, parse_key_script, registers_json, run, Limit, RunResult};
+use chip8::movie::Movie;
+use chip8::trace::{parse_address_range, parse_opcode_classes};
+use chip8::{Chip8, Quirks, RandomMode, TraceLevel, Tracer};
+
+const USAGE: &str = "\
+Usage: chip8-headless <rom> [options]
+  --frames <n>              run n frames of 1/60 s (default 600)
+  --cycles <n>              run n instructions instead
+  --cycles-per-frame <n>    instructions per frame (default 12)
+  --quirks <preset>         vip, chip48, schip1.0, schip1.1 (default) or xochip
+  --seed <n>                seed for CXNN (default 0)
+  --vip-random              CXNN uses the COSMAC VIP's generator
+  --keys <file>             key script, lines of '<frame> down|up <hex key>'
+  --movie <file>            replay a movie recorded with chip8 --record, its
+                            quirks, seed, speed and keys override the above
+  --format <fmt>            display dump: ascii (default), pbm or png
+  -o <file>                 write the display dump to a file instead of stdout
+  --regs <file>             write registers as JSON, - for stdout
+  --trace <file>            write an instruction trace, - for stdout
+  --trace-level <level>     events or instructions (default)
+  --trace-addr <range>      trace only instructions at these addresses, like 200-2FF
+  --trace-ops <classes>     trace only these opcode classes (first nibbles), like 8,D";
+
+struct Options {
+    rom: String,
+    limit: Limit,
+    cycles_per_frame: u64,
+    quirks: Quirks,
+    seed: u64,
+    random_mode: RandomMode,
+    keys: Option<String>,
+    movie: Option<String>,
+    format: String,
+    output: Option<String>,
+    regs: Option<String>,
+    trace: Option<String>,
+    trace_level: TraceLevel,
+    trace_addresses: Option<RangeInclusive<u16>>,
+    trace_classes: u16
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let options = match parse_args(&args) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{e}\n{USAGE}");
+            process::exit(2);
+        }
+    };
+
+    // Accept a path, or a file name under binaries/ like the emulator
+    let path = if Path::new(&options.rom).exists() {
+        Path::new(&options.rom).to_path_buf()
+    } else {
+        Path::new("binaries").join(&options.rom)
+    };
+    let rom = fs::read(&path).unwrap_or_else(|e| fail(&format!("Error reading {}: {e}", path.display())));
+
+    if let Some(file) = &options.movie {
+        let text = fs::read_to_string(file).unwrap_or_else(|e| fail(&format!("Error reading {file}: {e}")));
+        let movie = Movie::parse(&text).unwrap_or_else(|e| fail(&format!("{file}: {e}")));
+        let mut chip8 = movie.machine(&rom).unwrap_or_else(|e| fail(&format!("Error loading {}: {file} {e}", path.display())));
+        chip8.set_tracer(tracer(&options));
+        let result = run(&mut chip8, options.limit, movie.cycles_per_frame, &movie.events);
+        finish(&options, &mut chip8, result);
+        return;
+    }
+
+    let keys = match &options.keys {
+        Some(file) => {
+            let script = fs::read_to_string(file).unwrap_or_else(|e| fail(&format!("Error reading {file}: {e}")));
+            parse_key_script(&script).unwrap_or_else(|e| fail(&format!("{file}: {e}")))
+        }
+        None => Vec::new()
+    };
+
+    let mut chip8 = Chip8::new(options.quirks);
+    chip8.init();
+    chip8.set_seed(options.seed);
+    chip8.set_random_mode(options.random_mode);
+    if let Err(e) = chip8.load_bin(&rom) {
+        fail(&format!("Error loading {}: {e}", path.display()));
+    }
+
+    chip8.set_tracer(tracer(&options));
+    let result = run(&mut chip8, options.limit, options.cycles_per_frame, &keys);
+    finish(&options, &mut chip8, result);
+}
+
+fn tracer(options: &Options) -> Option<Tracer> {
+    let path = options.trace.as_deref()?;
+    let mut tracer = Tracer::to_file(options.trace_level, path).unwrap_or_else(|e| fail(&format!("Error creating {path}: {e}")));
+    tracer.set_addresses(options.trace_addresses.clone());
+    tracer.set_classes(options.trace_classes);
+    Some(tracer)
+}
+
+// Write the display and registers, and exit with an error if the run hit one
+fn finish(options: &Options, chip8: &mut Chip8, result: RunResult) {
+    // Exiting skips destructors, so the trace is flushed by hand
+    if let Some(Err(e)) = chip8.tracer_mut().map(|tracer| tracer.flush()) {
+        eprintln!("Error writing trace: {e}");
+    }
+
+    let dump = match options.format.as_str() {
+        "pbm" => display_pbm(chip8),
+        "png" => display_png(chip8),
+        _ => display_ascii(chip8).into_bytes()
+    };
+    write_output(options.output.as_deref(), &dump);
+
+    if let Some(regs) = options.regs.as_deref() {
+        let json = registers_json(chip8, &result) + "\n";
+        write_output(Some(regs).filter(|r| *r != "-"), json.as_bytes());
+    }
+
+    if let Some(e) = result.error {
+        eprintln!("Error: {e}");
+        process::exit(1);
+    }
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        rom: String::new(),
+        limit: Limit::Frames(600),
+        cycles_per_frame: 12,
+        quirks: Quirks::SUPER_CHIP_1_1,
+        seed: 0,
+        random_mode: RandomMode::Xorshift,
+        keys: None,
+        movie: None,
+        format: "ascii".to_string(),
+        output: None,
+        regs: None,
+        trace: None,
+        trace_level: TraceLevel::Instructions,
+        trace_addresses: None,
+        trace_classes: 0xFFFF
+    };
+    let mut rom = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().cloned().ok_or(format!("{arg} needs a value"));
+        let number = |text: String| text.parse::<u64>().map_err(|_| format!("invalid number '{text}' for {arg}"));
+
+        match arg.as_str() {
+            "--frames" => options.limit = Limit::Frames(number(value()?)?),
+            "--cycles" => options.limit = Limit::Cycles(number(value()?)?),
+            "--cycles-per-frame" => options.cycles_per_frame = number(value()?)?.max(1),
+            "--quirks" => {
+                let name = value()?;
+                options.quirks = Quirks::from_name(&name).ok_or(format!("unknown quirks preset '{name}'"))?;
+            }
+            "--seed" => options.seed = number(value()?)?,
+            "--vip-random" => options.random_mode = RandomMode::CosmacVip,
+            "--keys" => options.keys = Some(value()?),
+            "--movie" => options.movie = Some(value()?),
+            "--format" => {
+                options.format = value()?;
+                if !["ascii", "pbm", "png"].contains(&options.format.as_str()) {
+                    return Err(format!("unknown format '{}'", options.format));
+                }
+            }
+            "-o" => options.output = Some(value()?),
+            "--regs" => options.regs = Some(value()?),
+            "--trace" => options.trace = Some(value()?),
+            "--trace-level" => {
+                let name = value()?;
+                options.trace_level = TraceLevel::from_name(&name).ok_or(format!("unknown trace level '{name}'"))?;
+            }
+            "--trace-addr" => options.trace_addresses = Some(parse_address_range(&value()?)?),
+            "--trace-ops" => options.trace_classes = parse_opcode_classes(&value()?)?,
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                process::exit(0);
+            }
+            other if other.starts_with('-') => return Err(format!("unknown option '{other}'")),
+            other if rom.is_none() => rom = Some(other.to_string()),
+            other => return Err(format!("unexpected argument '{other}'"))
+        }
+    }
+
+    options.rom = rom.ok_or("missing ROM")?;
+    Ok(options)
+}
+
+// Write to a file, or to stdout without a path
+fn write_output(path: Option<&str>, data: &[u8]) {
+    let written = match path {
+        Some(path) => fs::write(path, data),
+        None => io::stdout().write_all(data)
+    };
+    if let Err(e) = written {
+        fail(&format!("Error writing output: {e}"));
+    }
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("{message}");
+    process::exit(1);
+}
@@ -0,0 +1,308 @@
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use sdl2::pixels::Color;
+
+use chip8::config::{hex_key_setting, rom_hash, Config, Entry};
+use chip8::gamepad::PadMap;
+use chip8::romdb::RomInfo;
+use chip8::trace::{parse_address_range, parse_opcode_classes};
+use chip8::{Quirks, RandomMode, TraceLevel};
+
+use crate::keymap::{HostKey, KeyMap, LAYOUT_NAMES};
+
+pub const USAGE: &str = "\
+Usage: chip8 <rom> [options]
+  --ips <n>                 instructions per second (default 720), rounded to
+                            a multiple of 60, as a frame runs whole instructions
+  --cycles-per-frame <n>    instructions per 60 Hz frame (default 12)
+  --scale <n>               window pixels per CHIP-8 pixel (default 20)
+  --fullscreen              fill the screen
+  --quirks <preset>         vip, chip48, schip1.0, schip1.1 (default) or xochip
+  --seed <n>                seed for CXNN, random by default
+  --vip-random              CXNN uses the COSMAC VIP's generator
+  --palette <colors>        2 or 4 hex colors: off, plane 1, plane 2, both
+                            (default 000000,ffffff,aaaaaa,555555)
+  --mute                    no sound
+  --layout <name>           keyboard keys for the keypad: qwerty (default),
+                            azerty, dvorak or numpad
+  --debug                   start paused with a debugger prompt on the console
+  --headless                run without a window and print the final screen
+  --frames <n>              frames to run with --headless (default 600)
+  --record <movie>          record keypad input, saved on quit
+  --play <movie>            replay a recorded movie
+  --trace <file>            write an instruction trace, - for stdout
+  --trace-level <level>     events or instructions (default)
+  --trace-addr <range>      trace only instructions at these addresses, like 200-2FF
+  --trace-ops <classes>     trace only these opcode classes (first nibbles), like 8,D
+  --config <file>           settings file instead of the default one
+  --no-config               ignore the settings file
+  -h, --help                show this help
+  -V, --version             show the version
+
+The ROM is a path, or a file name under binaries/.
+
+Settings are applied in this order, later ones win: the defaults above, the
+[global] section of the config file, the ROM database's recommendations, the
+ROM's [rom <sha1>] section, then command line options. See the README for the
+config file and database.";
+
+const DEFAULT_PALETTE: [Color; 4] = [Color::BLACK, Color::WHITE, Color::RGB(170, 170, 170), Color::RGB(85, 85, 85)];
+
+// Options without a value
+const FLAGS: [&str; 5] = ["fullscreen", "vip-random", "mute", "debug", "headless"];
+
+// Options that may also be set in the config file, besides quirk fields, key.<hex> and pad.<hex>
+const CONFIG_KEYS: [&str; 9] = ["ips", "cycles-per-frame", "scale", "fullscreen", "quirks", "vip-random", "palette", "mute", "layout"];
+
+// The command line before settings are resolved
+pub struct Args {
+    pub rom: PathBuf,
+    pub config: Option<PathBuf>, // --config
+    pub no_config: bool,
+    settings: Vec<(String, Option<String>)> // options in order, without the leading --
+}
+
+pub struct Options {
+    pub cycles_per_frame: u64,
+    pub scale: u32,
+    pub fullscreen: bool,
+    pub quirks: Quirks,
+    pub seed: Option<u64>,
+    pub random_mode: RandomMode,
+    pub palette: [Color; 4], // indexed by pixel value: the bitmask of planes a pixel is lit on
+    pub keymap: KeyMap,
+    pub padmap: PadMap, // controller buttons
+    pub mute: bool,
+    pub debug: bool,
+    pub headless: bool,
+    pub frames: u64,
+    pub record: Option<String>,
+    pub play: Option<String>,
+    pub trace: Option<String>,
+    pub trace_level: TraceLevel,
+    pub trace_addresses: Option<RangeInclusive<u16>>,
+    pub trace_classes: u16
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            cycles_per_frame: 12,
+            scale: 20,
+            fullscreen: false,
+            quirks: Quirks::SUPER_CHIP_1_1,
+            seed: None,
+            random_mode: RandomMode::Xorshift,
+            palette: DEFAULT_PALETTE,
+            keymap: KeyMap::default(),
+            padmap: PadMap::new(),
+            mute: false,
+            debug: false,
+            headless: false,
+            frames: 600,
+            record: None,
+            play: None,
+            trace: None,
+            trace_level: TraceLevel::Instructions,
+            trace_addresses: None,
+            trace_classes: 0xFFFF
+        }
+    }
+}
+
+pub fn parse_args(args: &[String]) -> Result<Args, String> {
+    let mut parsed = Args { rom: PathBuf::new(), config: None, no_config: false, settings: Vec::new() };
+    let mut rom = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                process::exit(0);
+            }
+            "-V" | "--version" => {
+                println!("chip8 {}", env!("CARGO_PKG_VERSION"));
+                process::exit(0);
+            }
+            "--config" => parsed.config = Some(args.next().ok_or("--config needs a value")?.into()),
+            "--no-config" => parsed.no_config = true,
+            other if other.starts_with("--") => {
+                let name = &other[2..];
+                let value = if FLAGS.contains(&name) { None } else { Some(args.next().cloned().ok_or(format!("{other} needs a value"))?) };
+                // Check it now, so mistakes are reported before the ROM is read
+                apply(&mut Options::default(), name, value.as_deref(), None)?;
+                parsed.settings.push((name.to_string(), value));
+            }
+            other if other.starts_with('-') => return Err(format!("unknown option '{other}'")),
+            other if rom.is_none() => rom = Some(other.to_string()),
+            other => return Err(format!("unexpected argument '{other}', only one ROM can be given"))
+        }
+    }
+
+    parsed.rom = find_rom(&rom.ok_or("missing ROM")?)?;
+    Ok(parsed)
+}
+
+// Resolve the settings for a run: defaults, global config entries, what the
+// ROM database recommends, the ROM's config section, then the command line
+pub fn options(args: &Args, config: &Config, config_path: &Path, rom: &[u8], info: Option<&RomInfo>) -> Result<Options, String> {
+    let mut options = Options::default();
+    let rom_section = format!("[rom {}]", rom_hash(rom));
+    let apply_entry = |options: &mut Options, section: &str, entry: &Entry| {
+        apply(options, &entry.key, Some(&entry.value), Some(section)).map_err(|e| format!("{}: line {}: {}", config_path.display(), entry.line, e))
+    };
+
+    for entry in config.global_entries() {
+        apply_entry(&mut options, "[global]", entry)?;
+    }
+    if let Some(info) = info {
+        if let Some(quirks) = info.quirks {
+            options.quirks = quirks;
+        }
+        if let Some(tickrate) = info.tickrate {
+            options.cycles_per_frame = tickrate;
+        }
+        let colors: Vec<Color> = info.colors.iter().map(|&(r, g, b)| Color::RGB(r, g, b)).collect();
+        if let Some(palette) = palette_from(&colors) {
+            options.palette = palette;
+        }
+    }
+    for entry in config.rom_entries(rom) {
+        apply_entry(&mut options, &rom_section, entry)?;
+    }
+    for (name, value) in &args.settings {
+        apply(&mut options, name, value.as_deref(), None)?;
+    }
+    options.padmap = PadMap::from_config(config, rom, info).map_err(|e| format!("{}: {}", config_path.display(), e))?;
+
+    if options.record.is_some() && options.play.is_some() {
+        return Err("--record and --play can't be used together".to_string());
+    }
+    if options.record.is_some() && options.headless {
+        return Err("--record needs a window, there is no input to record with --headless".to_string());
+    }
+    Ok(options)
+}
+
+// Set one option by name, from the command line or a config file section
+// (like "[global]"), which errors name. Flags are true without a value; the
+// config file writes them as true / false, and can also set single quirks,
+// keys and controller buttons.
+fn apply(options: &mut Options, name: &str, value: Option<&str>, section: Option<&str>) -> Result<(), String> {
+    let from_config = section.is_some();
+    let option = match section {
+        Some(section) => format!("{section} {name}"),
+        None => format!("--{name}")
+    };
+    if from_config && !CONFIG_KEYS.contains(&name) && !name.starts_with("key.") && !name.starts_with("pad.") && !Quirks::FIELD_NAMES.contains(&name) {
+        return Err(format!("unknown setting '{name}'"));
+    }
+
+    let flag = match value {
+        None | Some("true") | Some("1") => Ok(true),
+        Some("false") | Some("0") => Ok(false),
+        Some(other) => Err(format!("invalid value '{other}' for {option}, expected true or false"))
+    };
+    let value = || value.ok_or(format!("{option} needs a value"));
+    let number = |text: &str| text.parse::<u64>().map_err(|_| format!("invalid number '{text}' for {option}"));
+
+    match name {
+        // Frames run whole instructions, so 700 becomes 720
+        "ips" => options.cycles_per_frame = ((number(value()?)? + 30) / 60).max(1),
+        "cycles-per-frame" => options.cycles_per_frame = number(value()?)?.max(1),
+        "scale" => {
+            let scale = number(value()?)?;
+            options.scale = u32::try_from(scale).ok().filter(|s| (1..=100).contains(s)).ok_or(format!("{option} must be 1 - 100"))?;
+        }
+        "fullscreen" => options.fullscreen = flag?,
+        "quirks" => {
+            let name = value()?;
+            options.quirks = Quirks::from_name(name)
+                .ok_or(format!("unknown quirks preset '{name}', expected one of {}", Quirks::PRESET_NAMES.join(", ")))?;
+        }
+        "seed" => options.seed = Some(number(value()?)?),
+        "vip-random" => options.random_mode = if flag? { RandomMode::CosmacVip } else { RandomMode::Xorshift },
+        "palette" => options.palette = parse_palette(value()?, &option)?,
+        "mute" => options.mute = flag?,
+        "layout" => {
+            let name = value()?;
+            options.keymap = KeyMap::layout(name).ok_or(format!("unknown layout '{name}', expected one of {}", LAYOUT_NAMES.join(", ")))?;
+        }
+        "debug" => options.debug = flag?,
+        "headless" => options.headless = flag?,
+        "frames" => options.frames = number(value()?)?,
+        "record" => options.record = Some(value()?.to_string()),
+        "play" => options.play = Some(value()?.to_string()),
+        "trace" => options.trace = Some(value()?.to_string()),
+        "trace-level" => {
+            let name = value()?;
+            options.trace_level = TraceLevel::from_name(name).ok_or(format!("unknown trace level '{name}'"))?;
+        }
+        "trace-addr" => options.trace_addresses = Some(parse_address_range(value()?)?),
+        "trace-ops" => options.trace_classes = parse_opcode_classes(value()?)?,
+        key if from_config && key.starts_with("key.") => bind(&mut options.keymap, key, value()?)?,
+        // Controller buttons are read by PadMap::from_config
+        key if from_config && key.starts_with("pad.") => {}
+        quirk if from_config => options.quirks.set(quirk, value()?)?,
+        _ => return Err(format!("unknown option '{option}'"))
+    }
+    Ok(())
+}
+
+// key.<hex> = <key names>: the host keys for a hex key, replacing the
+// layout's. Names are SDL's, like W, Up or Keypad 5, or scan:<name> for a
+// key by its position.
+fn bind(keymap: &mut KeyMap, key: &str, names: &str) -> Result<(), String> {
+    let hex = hex_key_setting(key, "key.").ok_or(format!("unknown setting '{key}'"))??;
+    let keys = names.split(',').map(str::trim).filter(|n| !n.is_empty()).map(HostKey::from_name).collect::<Result<Vec<_>, _>>()?;
+    keymap.set(hex, &keys);
+    Ok(())
+}
+
+// A path, or a file name under binaries/ as before there were options
+fn find_rom(name: &str) -> Result<PathBuf, String> {
+    let path = Path::new(name);
+    if path.exists() {
+        return Ok(path.to_path_buf());
+    }
+    let fallback = Path::new("binaries").join(name);
+    if fallback.exists() {
+        return Ok(fallback);
+    }
+    Err(format!("ROM not found: {name} (also looked for {})", fallback.display()))
+}
+
+// Comma separated hex colors, like 000000,ffffff. With two colors the XO-CHIP
+// plane 2 and overlap colors are blends of them. option is where the text
+// came from, for the error.
+fn parse_palette(text: &str, option: &str) -> Result<[Color; 4], String> {
+    let colors = text
+        .split(',')
+        .map(|c| {
+            let c = c.trim().trim_start_matches('#');
+            match u32::from_str_radix(c, 16) {
+                Ok(rgb) if c.len() == 6 => Ok(Color::RGB((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8)),
+                _ => Err(format!("invalid color '{c}' for {option}, expected 6 hex digits like ff8800"))
+            }
+        })
+        .collect::<Result<Vec<Color>, String>>()?;
+
+    palette_from(&colors).ok_or(format!("{option} takes 2 or 4 colors, found {}", colors.len()))
+}
+
+fn palette_from(colors: &[Color]) -> Option<[Color; 4]> {
+    match *colors {
+        [off, on] => Some([off, on, blend(off, on, 2), blend(off, on, 1)]),
+        [off, on, plane2, both] => Some([off, on, plane2, both]),
+        _ => None
+    }
+}
+
+// thirds/3 of the way from a to b
+fn blend(a: Color, b: Color, thirds: u16) -> Color {
+    let mix = |a: u8, b: u8| ((a as u16 * (3 - thirds) + b as u16 * thirds) / 3) as u8;
+    Color::RGB(mix(a.r, b.r), mix(a.g, b.g), mix(a.b, b.b))
+}
@@ -0,0 +1,121 @@
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::sha1::sha1_hex;
+
+// Settings file, INI style: `key = value` lines under section headers, with
+// # or ; starting a comment line. [global] holds defaults for every ROM and
+// [rom <sha1>] sections override them for the ROM with that SHA-1:
+//
+//     [global]
+//     scale = 15
+//     palette = 1a1c2c,f4f4f4
+//
+//     # Tetris
+//     [rom 0123456789abcdef0123456789abcdef01234567]
+//     quirks = vip
+//     key.4 = Left
+//
+// Lines before the first header count as global. What the keys mean is up to
+// the frontend, this only reads them.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Config {
+    global: Vec<Entry>,
+    roms: Vec<(String, Vec<Entry>)> // lowercase hex SHA-1 and its entries
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Entry {
+    pub key: String,
+    pub value: String,
+    pub line: usize // 1-based, for error messages
+}
+
+impl Config {
+    pub fn parse(text: &str) -> Result<Config, String> {
+        let mut config = Config::default();
+        let mut section: Option<usize> = None; // index into roms, None for global
+
+        for (n, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+                continue;
+            }
+
+            if let Some(header) = line.strip_prefix('[') {
+                let header = header.strip_suffix(']').ok_or(format!("line {}: expected ']'", n + 1))?.trim();
+                section = match header.split_whitespace().collect::<Vec<_>>()[..] {
+                    ["global"] => None,
+                    ["rom", hash] if hash.len() == 40 && hash.chars().all(|c| c.is_ascii_hexdigit()) => {
+                        config.roms.push((hash.to_lowercase(), Vec::new()));
+                        Some(config.roms.len() - 1)
+                    }
+                    _ => return Err(format!("line {}: unknown section [{}], expected [global] or [rom <sha1>]", n + 1, header))
+                };
+                continue;
+            }
+
+            let (key, value) = line.split_once('=').ok_or(format!("line {}: expected 'key = value'", n + 1))?;
+            let entry = Entry { key: key.trim().to_string(), value: value.trim().to_string(), line: n + 1 };
+            match section {
+                Some(i) => config.roms[i].1.push(entry),
+                None => config.global.push(entry)
+            }
+        }
+
+        Ok(config)
+    }
+
+    // Read a config file, a missing one is an empty config
+    pub fn load(path: &Path) -> Result<Config, String> {
+        match fs::read_to_string(path) {
+            Ok(text) => Config::parse(&text).map_err(|e| format!("{}: {}", path.display(), e)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Config::default()),
+            Err(e) => Err(format!("{}: {}", path.display(), e))
+        }
+    }
+
+    // Settings for a ROM in the order to apply them: global ones first, then
+    // the ROM's own, so later entries win
+    pub fn entries_for(&self, rom: &[u8]) -> Vec<&Entry> {
+        self.global_entries().iter().chain(self.rom_entries(rom)).collect()
+    }
+
+    pub fn global_entries(&self) -> &[Entry] {
+        &self.global
+    }
+
+    // Entries of the ROM's [rom <sha1>] sections
+    pub fn rom_entries(&self, rom: &[u8]) -> Vec<&Entry> {
+        let hash = rom_hash(rom);
+        self.roms.iter().filter(|(h, _)| *h == hash).flat_map(|(_, entries)| entries).collect()
+    }
+}
+
+// The hex key a per-key setting is for, like 4 for key.4 with prefix "key.".
+// None if the setting doesn't start with prefix.
+pub fn hex_key_setting(key: &str, prefix: &str) -> Option<Result<u8, String>> {
+    let hex = key.strip_prefix(prefix)?;
+    Some(u8::from_str_radix(hex, 16).ok().filter(|&k| k < 16).ok_or(format!("invalid hex key '{hex}' in {key}")))
+}
+
+// The key of a ROM's section, its SHA-1 in lowercase hex
+pub fn rom_hash(rom: &[u8]) -> String {
+    sha1_hex(rom)
+}
+
+// Where the config file lives when not given: chip8/config.ini in the
+// platform's config directory
+pub fn default_path() -> Option<PathBuf> {
+    let var = |name| env::var_os(name).filter(|v| !v.is_empty()).map(PathBuf::from);
+    let dir = if cfg!(windows) {
+        var("APPDATA")?
+    } else if cfg!(target_os = "macos") {
+        var("HOME")?.join("Library/Application Support")
+    } else {
+        var("XDG_CONFIG_HOME").or_else(|| Some(var("HOME")?.join(".config")))?
+    };
+    Some(dir.join("chip8").join("config.ini"))
+}
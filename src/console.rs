@@ -0,0 +1,26 @@
+use std::io::{self, BufRead, Write};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+// Read console lines on a separate thread so the SDL loop keeps running
+pub fn spawn() -> Receiver<String> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        for line in io::stdin().lock().lines() {
+            match line {
+                Ok(line) => {
+                    if sender.send(line).is_err() {
+                        break;
+                    }
+                }
+                Err(_) => break
+            }
+        }
+    });
+    receiver
+}
+
+pub fn prompt() {
+    print!("(chip8) ");
+    io::stdout().flush().unwrap();
+}
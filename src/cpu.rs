@@ -1,31 +1,96 @@
-use rand::{rngs::ThreadRng, Rng};
-use sdl2::keyboard::Keycode;
+mod builder;
+mod frame;
+mod savestate;
+mod undo;
+#[cfg(test)]
+mod tests;
+
+use std::ops::Range;
+
+use crate::error::Chip8Error;
+use crate::instruction::{decode, Instruction};
+use crate::quirks::{MemoryIncrement, Quirks};
+use crate::rng::{RandomMode, Rng};
+use crate::trace::Tracer;
+
+pub use builder::Chip8Builder;
+pub use frame::FrameResult;
+use undo::UndoLog;
 
 pub struct Chip8 {
-    memory: [u8; 4096], // 4096 bytes
-    display: [u8; 64 * 32], // 64 * 32 pixels
+    memory: Vec<u8>, // 4 KiB, or 64 KiB with the extended_memory quirk
+    display: [u8; DISPLAY_WIDTH * DISPLAY_HEIGHT], // 128 * 64 pixels, lores uses the first 64 * 32
+    plane: u8, // XO-CHIP bitplanes selected for drawing (bit 0: plane 1, bit 1: plane 2)
+    hires: bool, // SUPER-CHIP 128 * 64 mode
     pc: u16, // program counter
     i: u16, // memory pointer
     stack: Vec<u16>,
     delay_timer: u8,
     sound_timer: u8,
     v: [u8; 16], // variable registers
-    modern: bool, // toggle for modern implementations of ambiguous instructions
-    rng: ThreadRng,
+    quirks: Quirks, // behaviour of ambiguous instructions
+    vblank: bool, // set on each 60 Hz tick, used by the display wait quirk
+    rng: Rng,
+    random_mode: RandomMode, // CXNN algorithm
     keypad: [bool; 16],
     key_pressed: bool,
-    pressed_key: usize
+    pressed_key: usize,
+    rpl: [u8; 16], // SUPER-CHIP RPL user flags (FX75 / FX85)
+    audio_pattern: Option<[u8; 16]>, // XO-CHIP 1-bit audio samples (F002)
+    pitch: u8, // XO-CHIP audio pitch (FX3A)
+    access_log: Option<Vec<MemoryAccess>>, // memory accessed by the last instruction, when enabled
+    undo: Option<UndoLog>, // what recent instructions overwrote, when reverse execution is enabled
+    tracer: Option<Tracer>,
+    frame_cycles: u64 // instructions run of a frame run_frame_until stopped in
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AccessKind {
+    Read,
+    Write
+}
+
+// A range of memory read or written by an instruction
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MemoryAccess {
+    pub kind: AccessKind,
+    pub start: usize,
+    pub len: usize
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum ExecutionEffect {
     NoEffect,
     DisplayUpdate,
     JumpToSelf,
     WaitingForKey,
-    Sound
+    Sound,
+    Exit
 }
 
-const FONT: [u8; 80] = [
+// Largest (hires) display size
+pub const DISPLAY_WIDTH: usize = 128;
+pub const DISPLAY_HEIGHT: usize = 64;
+
+// Memory sizes, see the extended_memory quirk
+const MEMORY_SIZE: usize = 0x1000;
+const EXTENDED_MEMORY_SIZE: usize = 0x10000;
+
+// Maximum depth of nested subroutine calls
+const STACK_SIZE: usize = 16;
+
+// Programs are loaded and start at 0x200
+const PROGRAM_START: usize = 0x200;
+
+// Where the fonts are loaded in memory
+const FONT_ADDR: usize = 0x000;
+const BIG_FONT_ADDR: usize = 0x050;
+
+// The page the COSMAC VIP CXNN reads bytes from. The VIP read its own code at
+// 0x100; the interpreter area here holds the fonts, in the page below that.
+const VIP_RANDOM_PAGE: usize = 0x000;
+
+pub const FONT: [u8; 80] = [
     0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
     0x20, 0x60, 0x20, 0x20, 0x70, // 1
     0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
@@ -44,507 +109,755 @@ const FONT: [u8; 80] = [
     0xF0, 0x80, 0xF0, 0x80, 0x80, // F
 ];
 
+// SUPER-CHIP 8x10 font, A - F as in Octo
+pub const BIG_FONT: [u8; 160] = [
+    0x3C, 0x7E, 0xE7, 0xC3, 0xC3, 0xC3, 0xC3, 0xE7, 0x7E, 0x3C, // 0
+    0x18, 0x38, 0x58, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x3C, // 1
+    0x3E, 0x7F, 0xC3, 0x06, 0x0C, 0x18, 0x30, 0x60, 0xFF, 0xFF, // 2
+    0x3C, 0x7E, 0xC3, 0x03, 0x0E, 0x0E, 0x03, 0xC3, 0x7E, 0x3C, // 3
+    0x06, 0x0E, 0x1E, 0x36, 0x66, 0xC6, 0xFF, 0xFF, 0x06, 0x06, // 4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFE, 0x03, 0xC3, 0x7E, 0x3C, // 5
+    0x3E, 0x7C, 0xE0, 0xC0, 0xFC, 0xFE, 0xC3, 0xC3, 0x7E, 0x3C, // 6
+    0xFF, 0xFF, 0x03, 0x06, 0x0C, 0x18, 0x30, 0x60, 0x60, 0x60, // 7
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7E, 0x7E, 0xC3, 0xC3, 0x7E, 0x3C, // 8
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7F, 0x3F, 0x03, 0x03, 0x3E, 0x7C, // 9
+    0x3C, 0x7E, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, 0xC3, // A
+    0xFC, 0xFE, 0xC3, 0xC3, 0xFE, 0xFE, 0xC3, 0xC3, 0xFE, 0xFC, // B
+    0x3C, 0x7E, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0x7E, 0x3C, // C
+    0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFC, 0xC0, 0xC0, 0xFF, 0xFF, // E
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFC, 0xC0, 0xC0, 0xC0, 0xC0, // F
+];
+
 impl Chip8 {
-    pub fn new(modern: bool) -> Chip8 {
+    pub fn new(quirks: Quirks) -> Chip8 {
         Chip8 {
-            memory: [0; 4096],
-            display: [0; 64 * 32],
-            pc: 0x200, // instructions start at 0x200
+            memory: vec![0; memory_size(&quirks)],
+            display: [0; DISPLAY_WIDTH * DISPLAY_HEIGHT],
+            plane: 1,
+            hires: false,
+            pc: PROGRAM_START as u16, // instructions start at 0x200
             i: 0,
             stack: Vec::new(),
             delay_timer: 0,
             sound_timer: 0,
             v: [0; 16],
-            modern: modern,
-            rng: rand::thread_rng(),
+            quirks,
+            vblank: true,
+            rng: Rng::from_entropy(),
+            random_mode: RandomMode::Xorshift,
             keypad: [false; 16],
             key_pressed: false,
-            pressed_key: 0
+            pressed_key: 0,
+            rpl: [0; 16],
+            audio_pattern: None,
+            pitch: 64,
+            access_log: None,
+            undo: None,
+            tracer: None,
+            frame_cycles: 0
         }
     }
 
     pub fn init(&mut self) {
-        // Load font into memory, from beginning
-        for (i, &b) in FONT.iter().enumerate() {
-            self.memory[i] = b;
-        }
+        // Load both fonts into memory, from beginning
+        self.memory[FONT_ADDR..FONT_ADDR + FONT.len()].copy_from_slice(&FONT);
+        self.memory[BIG_FONT_ADDR..BIG_FONT_ADDR + BIG_FONT.len()].copy_from_slice(&BIG_FONT);
     }
 
     // Load Chip8 binary into memory starting at 0x200
-    pub fn load_bin(&mut self, bin: &[u8]) {
-        for (i, &b) in bin.iter().enumerate() {
-            self.memory[0x200 + i] = b;
+    pub fn load_bin(&mut self, bin: &[u8]) -> Result<(), Chip8Error> {
+        let max = self.memory.len() - PROGRAM_START;
+        if bin.len() > max {
+            return Err(Chip8Error::RomTooLarge { size: bin.len(), max });
+        }
+
+        self.memory[PROGRAM_START..PROGRAM_START + bin.len()].copy_from_slice(bin);
+        Ok(())
+    }
+
+    // Pixels of the active resolution, row by row.
+    // Each pixel is a bitmask of the planes it is lit on (0 - 3).
+    pub fn display(&self) -> &[u8] {
+        let (width, height) = self.resolution();
+        &self.display[..width * height]
+    }
+
+    // Active (width, height): 64 * 32, or 128 * 64 in hires mode
+    pub fn resolution(&self) -> (usize, usize) {
+        if self.hires {
+            (DISPLAY_WIDTH, DISPLAY_HEIGHT)
+        } else {
+            (DISPLAY_WIDTH / 2, DISPLAY_HEIGHT / 2)
         }
     }
 
-    pub fn display(&self) -> &[u8; 64 * 32] {
-        &self.display
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
     }
 
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+        self.memory.resize(memory_size(&quirks), 0);
+    }
+
+    // Called at 60 Hz: counts the timers down and signals vertical blank
     pub fn decrement_timers(&mut self) {
+        self.vblank = true;
+        if self.random_mode == RandomMode::CosmacVip {
+            self.rng.tick_vip();
+        }
         if self.delay_timer > 0 { self.delay_timer -= 1; }
         if self.sound_timer > 0 { self.sound_timer -= 1; }
     }
 
+    pub fn pc(&self) -> u16 {
+        self.pc
+    }
+
+    pub fn i(&self) -> u16 {
+        self.i
+    }
+
+    pub fn v(&self) -> &[u8; 16] {
+        &self.v
+    }
+
+    // Return addresses, innermost call last
+    pub fn stack(&self) -> &[u16] {
+        &self.stack
+    }
+
+    pub fn memory(&self) -> &[u8] {
+        &self.memory
+    }
+
+    // Restart the CXNN generator from a seed, for reproducible runs
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rng = Rng::new(seed);
+    }
+
+    pub fn random_mode(&self) -> RandomMode {
+        self.random_mode
+    }
+
+    pub fn set_random_mode(&mut self, mode: RandomMode) {
+        self.random_mode = mode;
+    }
+
+    // Record the memory each instruction accesses, for watchpoints
+    pub fn set_access_logging(&mut self, enabled: bool) {
+        self.access_log = if enabled { Some(Vec::new()) } else { None };
+    }
+
+    // Memory accessed by the last fetch and execute, empty unless logging is enabled
+    pub fn accesses(&self) -> &[MemoryAccess] {
+        self.access_log.as_deref().unwrap_or(&[])
+    }
+
+    // Write a trace of instructions and key events, None turns tracing off
+    pub fn set_tracer(&mut self, tracer: Option<Tracer>) {
+        self.tracer = tracer;
+    }
+
+    pub fn tracer_mut(&mut self) -> Option<&mut Tracer> {
+        self.tracer.as_mut()
+    }
+
+    pub fn delay_timer(&self) -> u8 {
+        self.delay_timer
+    }
+
     pub fn sound_timer(&self) -> u8 {
         self.sound_timer
     }
 
-    pub fn key_down(&mut self, key: Keycode) {
-        println!("Key down: {key}");
-        match key {
-            Keycode::Num1 => self.press_key(0x1), // 1
-            Keycode::Num2 => self.press_key(0x2), // 2
-            Keycode::Num3 => self.press_key(0x3), // 3
-            Keycode::Num4 => self.press_key(0xC), // C
-            Keycode::Q => self.press_key(0x4), // 4
-            Keycode::W => self.press_key(0x5), // 5
-            Keycode::E => self.press_key(0x6), // 6
-            Keycode::R => self.press_key(0xD), // D
-            Keycode::A => self.press_key(0x7), // 7
-            Keycode::S => self.press_key(0x8), // 8
-            Keycode::D => self.press_key(0x9), // 9
-            Keycode::F => self.press_key(0xE), // E
-            Keycode::Z => self.press_key(0xA), // A
-            Keycode::X => self.press_key(0x0), // 0
-            Keycode::C => self.press_key(0xB), // B
-            Keycode::V => self.press_key(0xF), // F
-            _ => println!("Unmapped key: {key}")
+    // XO-CHIP audio pattern, None until a program loads one with F002
+    pub fn audio_pattern(&self) -> Option<&[u8; 16]> {
+        self.audio_pattern.as_ref()
+    }
+
+    // Rate in Hz at which the bits of the audio pattern are played
+    pub fn playback_rate(&self) -> f32 {
+        4000.0 * 2f32.powf((self.pitch as f32 - 64.0) / 48.0)
+    }
+
+    // Press or release a key on the hex keypad (0x0 - 0xF), other keys are
+    // ignored
+    pub fn set_key(&mut self, hex: u8, pressed: bool) {
+        if hex as usize >= self.keypad.len() {
+            return;
+        }
+        self.trace_event(format_args!("key {} {:X}", if pressed { "down" } else { "up" }, hex));
+        self.keypad[hex as usize] = pressed;
+        if pressed {
+            self.pressed_key = hex as usize;
+            self.key_pressed = true;
         }
     }
 
-    fn press_key(&mut self, n: usize) {
-        self.keypad[n] = true;
-        self.pressed_key = n;
-        self.key_pressed = true;
-    }
-
-    pub fn key_up(&mut self, key: Keycode) {
-        println!("Key up: {key}");
-        match key {
-            Keycode::Num1 => self.keypad[0x1] = false, // 1
-            Keycode::Num2 => self.keypad[0x2] = false, // 2
-            Keycode::Num3 => self.keypad[0x3] = false, // 3
-            Keycode::Num4 => self.keypad[0xC] = false, // C
-            Keycode::Q => self.keypad[0x4] = false, // 4
-            Keycode::W => self.keypad[0x5] = false, // 5
-            Keycode::E => self.keypad[0x6] = false, // 6
-            Keycode::R => self.keypad[0xD] = false, // D
-            Keycode::A => self.keypad[0x7] = false, // 7
-            Keycode::S => self.keypad[0x8] = false, // 8
-            Keycode::D => self.keypad[0x9] = false, // 9
-            Keycode::F => self.keypad[0xE] = false, // E
-            Keycode::Z => self.keypad[0xA] = false, // A
-            Keycode::X => self.keypad[0x0] = false, // 0
-            Keycode::C => self.keypad[0xB] = false, // B
-            Keycode::V => self.keypad[0xF] = false, // F
-            _ => println!("Unmapped key: {key}")
+    // Whether a key on the hex keypad is held
+    pub fn key(&self, hex: u8) -> bool {
+        self.keypad.get(hex as usize).copied().unwrap_or(false)
+    }
+
+    // Side effect of FX55 / FX65 on i
+    fn increment_i_after_transfer(&mut self, x: usize) {
+        match self.quirks.memory_increment {
+            MemoryIncrement::None => {}
+            MemoryIncrement::X => self.i = self.i.wrapping_add(x as u16),
+            MemoryIncrement::XPlusOne => self.i = self.i.wrapping_add((x + 1) as u16)
+        }
+    }
+
+    // Memory range for a len byte access at addr, or an error naming the instruction
+    fn mem_range(&mut self, kind: AccessKind, addr: usize, len: usize, pc: u16, opcode: u16) -> Result<Range<usize>, Chip8Error> {
+        if addr + len > self.memory.len() {
+            return Err(Chip8Error::MemoryOutOfBounds { pc, opcode, address: addr + len - 1 });
+        }
+        self.log_access(kind, addr, len);
+        if kind == AccessKind::Write {
+            self.record_write(addr, len);
+        }
+        Ok(addr..addr + len)
+    }
+
+    fn trace_event(&mut self, message: std::fmt::Arguments) {
+        if let Some(tracer) = &mut self.tracer {
+            tracer.event(message);
+        }
+    }
+
+    fn log_access(&mut self, kind: AccessKind, start: usize, len: usize) {
+        if let Some(log) = &mut self.access_log {
+            log.push(MemoryAccess { kind, start, len });
+        }
+    }
+
+    // Skip the next instruction, F000 NNNN is 4 bytes long
+    fn skip(&mut self) {
+        let next = self.pc as usize;
+        if self.memory.get(next..next + 2) == Some(&[0xF0, 0x00]) {
+            self.pc = self.pc.wrapping_add(4);
+        } else {
+            self.pc = self.pc.wrapping_add(2);
+        }
+    }
+
+    // Draw an 8xN sprite, or a 16x16 sprite when n is 0, from memory at i.
+    // With both XO-CHIP planes selected the data for plane 2 follows plane 1.
+    fn draw_sprite(&mut self, x: usize, y: usize, n: usize) -> ExecutionEffect {
+        let (width, height) = self.resolution();
+        let (sprite_width, rows) = if n == 0 { (16, 16) } else { (8, n) };
+        let row_bytes = sprite_width / 8;
+
+        // Starting position wraps, the sprite itself is clipped or wrapped
+        let vx = (self.v[x] as usize) % width;
+        let vy = (self.v[y] as usize) % height;
+        self.v[0xF] = 0;
+
+        let mut addr = self.i as usize;
+        for layer in [1, 2] {
+            if self.plane & layer == 0 {
+                continue;
+            }
+
+            for row in 0..rows {
+                let spr_row = if row_bytes == 2 {
+                    ((self.memory[addr] as u16) << 8) | self.memory[addr + 1] as u16
+                } else {
+                    (self.memory[addr] as u16) << 8
+                };
+                addr += row_bytes;
+
+                // Iterate bit by bit (MSB to LSB)
+                for col in 0..sprite_width {
+                    let bit = (spr_row >> (15 - col)) & 1;
+                    if bit == 1 {
+                        let draw_x = vx + col;
+                        let draw_y = vy + row;
+                        if self.quirks.clip_sprites && (draw_x >= width || draw_y >= height) {
+                            continue;
+                        }
+
+                        let display_index = (draw_y % height) * width + (draw_x % width);
+
+                        if self.display[display_index] & layer != 0 {
+                            self.v[0xF] = 1;
+                        }
+                        self.display[display_index] ^= layer;
+                    }
+                }
+            }
+        }
+
+        ExecutionEffect::DisplayUpdate
+    }
+
+    // Scroll the selected planes by dx, dy pixels of the active resolution, filling with blank
+    fn scroll(&mut self, dx: isize, dy: isize) {
+        let (width, height) = self.resolution();
+        let old = self.display;
+
+        for y in 0..height {
+            for x in 0..width {
+                let src_x = x as isize - dx;
+                let src_y = y as isize - dy;
+                let in_bounds = src_x >= 0 && src_y >= 0 && (src_x as usize) < width && (src_y as usize) < height;
+                let moved = if in_bounds {
+                    old[(src_y as usize) * width + src_x as usize] & self.plane
+                } else {
+                    0
+                };
+                let index = y * width + x;
+                self.display[index] = (self.display[index] & !self.plane) | moved;
+            }
         }
     }
 
+    // Clear the selected planes
+    fn clear(&mut self) {
+        for px in self.display.iter_mut() {
+            *px &= !self.plane;
+        }
+    }
+
+    // Switch between 64 * 32 and 128 * 64, clearing the screen
+    fn set_hires(&mut self, hires: bool) {
+        self.hires = hires;
+        self.display.fill(0);
+    }
+
     // Fetch the instruction from memory at program counter
-    pub fn fetch(&mut self) -> u16 {
+    pub fn fetch(&mut self) -> Result<u16, Chip8Error> {
+        let addr = self.pc as usize;
+        // The next pc has to fit in 16 bits too, with 64 KiB the last word can't run
+        let next = self.pc.checked_add(2).filter(|_| addr + 2 <= self.memory.len());
+        let Some(next) = next else {
+            return Err(Chip8Error::PcOutOfBounds { pc: self.pc });
+        };
+
+        // A new instruction starts, forget the accesses of the last one
+        if let Some(log) = &mut self.access_log {
+            log.clear();
+        }
+        self.log_access(AccessKind::Read, addr, 2);
+
         // Get both bytes, combine to a single 16 bit value and return
-        let b1 = self.memory[self.pc as usize] as u16;
-        let b2 = self.memory[(self.pc + 1) as usize] as u16;
-        
-        self.pc += 2;
+        let b1 = self.memory[addr] as u16;
+        let b2 = self.memory[addr + 1] as u16;
+
+        self.pc = next;
 
         // Left shift first byte by 8, OR with second byte to get both in one
-        (b1 << 8) | b2
+        Ok((b1 << 8) | b2)
+    }
+
+    pub fn execute(&mut self, opcode: u16) -> Result<ExecutionEffect, Chip8Error> {
+        if self.undo.is_none() {
+            return self.execute_instruction(opcode);
+        }
+
+        let pending = self.begin_undo(opcode);
+        let effect = self.execute_instruction(opcode);
+        self.end_undo(pending);
+        effect
     }
 
-    pub fn execute(&mut self, opcode: u16) -> ExecutionEffect {
-        // Extract 16 bits into 4 nibbles
-        let n1 = opcode & 0xF000;
-        let n2 = opcode & 0x0F00;
-        let n3 = opcode & 0x00F0;
-        let n4 = opcode & 0x000F;
+    fn execute_instruction(&mut self, opcode: u16) -> Result<ExecutionEffect, Chip8Error> {
+        // Address of this instruction, for errors
+        let pc = self.pc.wrapping_sub(2);
+
+        let instruction = decode(opcode);
+        if let Some(tracer) = &mut self.tracer {
+            tracer.instruction(pc, opcode, &instruction, &self.v, self.i);
+        }
 
-        match opcode {
+        match instruction {
             // 00E0: Clear screen
-            0x00E0 => {
-                println!("Opcode: {:#X} (Clear screen)", opcode);
-                self.display.fill(0);
-            }
+            Instruction::Clear => self.clear(),
 
             // 00EE: Return
-            0x00EE => {
-                println!("Opcode: {:#X} (Return)", opcode);
-                self.pc = self.stack.pop().expect("Stack empty.");
-            }
-
-            _ => {
-                match n1 {
-                    // 1NNN: jump to 0x0NNN
-                    0x1000 => {
-                        println!("Opcode: {:#X} (Jump)", opcode);
-                        let old_pc = self.pc;
-                        self.pc = n2 | n3 | n4;
-                        if self.pc == old_pc - 2 {
-                            return ExecutionEffect::JumpToSelf;
-                        }
-                    }
+            Instruction::Return => {
+                self.pc = self.stack.pop().ok_or(Chip8Error::StackUnderflow { pc, opcode })?;
+            }
 
-                    // 2NNN: push to stack and jump
-                    0x2000 => {
-                        println!("Opcode: {:#X} (Subroutine)", opcode);
-                        self.stack.push(self.pc);
-                        self.pc = n2 | n3 | n4;
-                    }
+            // 00CN: scroll down N pixels
+            Instruction::ScrollDown { n } => {
+                self.scroll(0, n as isize);
+                return Ok(ExecutionEffect::DisplayUpdate);
+            }
 
-                    // 3XNN: skip next if v[X] = NN
-                    0x3000 => {
-                        println!("Opcode: {:#X} (Skip Equal)", opcode);
-                        let r = (n2 >> 8) as usize;
-                        let val = (n3 | n4) as u8;
-                        if self.v[r] == val {
-                            self.pc += 2;
-                        }
-                    }
+            // 00FB: scroll right 4 pixels
+            Instruction::ScrollRight => {
+                self.scroll(4, 0);
+                return Ok(ExecutionEffect::DisplayUpdate);
+            }
 
-                    // 4XNN: skip next if v[X] != NN
-                    0x4000 => {
-                        println!("Opcode: {:#X} (Skip Not Equal)", opcode);
-                        let r = (n2 >> 8) as usize;
-                        let val = (n3 | n4) as u8;
-                        if self.v[r] != val {
-                            self.pc += 2;
-                        }
-                    }
+            // 00FC: scroll left 4 pixels
+            Instruction::ScrollLeft => {
+                self.scroll(-4, 0);
+                return Ok(ExecutionEffect::DisplayUpdate);
+            }
 
-                    // 5XY0: skip next if v[X] = v[Y]
-                    0x5000 => {
-                        println!("Opcode: {:#X} (Skip Reg Equal)", opcode);
-                        let r1 = (n2 >> 8) as usize;
-                        let r2 = (n3 >> 4) as usize;
-                        if self.v[r1] == self.v[r2] {
-                            self.pc += 2
-                        }
-                    }
+            // 00FD: exit the interpreter
+            Instruction::Exit => {
+                self.pc -= 2;
+                return Ok(ExecutionEffect::Exit);
+            }
 
-                    // 6XNN: set register VX to NN
-                    0x6000 => {
-                        println!("Opcode: {:#X} (Set)", opcode);
-                        let r = (n2 >> 8) as usize;
-                        let val = (n3 | n4) as u8;
-                        self.v[r] = val;
-                    }
+            // 00FE: low resolution (64 * 32)
+            Instruction::LowRes => {
+                self.set_hires(false);
+                return Ok(ExecutionEffect::DisplayUpdate);
+            }
 
-                    // 7XNN: add NN to value in VX
-                    0x7000 => {
-                        println!("Opcode: {:#X} (Add)", opcode);
-                        let r = (n2 >> 8) as usize;
-                        let val = (n3 | n4) as u8;
+            // 00FF: high resolution (128 * 64)
+            Instruction::HighRes => {
+                self.set_hires(true);
+                return Ok(ExecutionEffect::DisplayUpdate);
+            }
 
-                        // Prevent overflow panic, wrap instead
-                        self.v[r] = self.v[r].wrapping_add(val);
-                    }
+            // 1NNN: jump to 0x0NNN
+            Instruction::Jump { nnn } => {
+                self.pc = nnn;
+                if nnn == pc {
+                    return Ok(ExecutionEffect::JumpToSelf);
+                }
+            }
 
-                    // 8XYN: Logical and arithmetic
-                    0x8000 => {
-                        let x = (n2 >> 8) as usize;
-                        let y = (n3 >> 4) as usize;
-
-                        match n4 {
-                            // 0: set v[X] = v[Y]
-                            0x0 => {
-                                println!("Opcode: {:#X} (Set Register)", opcode);
-                                self.v[x] = self.v[y];
-                            }
-
-                            // 1: set v[X] = v[X] | v[Y]
-                            0x1 => {
-                                println!("Opcode: {:#X} (Binary OR)", opcode);
-                                self.v[x] = self.v[x] | self.v[y];
-                            }
-
-                            // 2: set v[X] = v[X] & v[Y]
-                            0x2 => {
-                                println!("Opcode: {:#X} (Binary AND)", opcode);
-                                self.v[x] = self.v[x] & self.v[y];
-                            }
-
-                            // 3: set v[X] = v[X] ^ v[Y]
-                            0x3 => {
-                                println!("Opcode: {:#X} (XOR)", opcode);
-                                self.v[x] = self.v[x] ^ self.v[y];
-                            }
-
-                            // 4: set v[X] = v[X] + v[Y], set v[F] to carry
-                            0x4 => {
-                                println!("Opcode: {:#X} (Add Registers)", opcode);
-                                let (sum, overflow) = self.v[x].overflowing_add(self.v[y]);
-                                self.v[x] = sum;
-                                self.v[0xF] = if overflow { 1 } else { 0 };
-                            }
-
-                            // 5: set v[X] = v[X] - v[Y], v[F] = 1 if v[X] > v[Y] else 0
-                            0x5 => {
-                                println!("Opcode: {:#X} (Subtract X-Y)", opcode);
-                                let (res, underflow) = self.v[x].overflowing_sub(self.v[y]);
-                                self.v[x] = res;
-                                self.v[0xF] = if underflow { 0 } else { 1 };
-                            }
-
-                            /*
-                                6: right shift v[X]
-                                modern: ignore Y
-                                original: set v[X] = v[Y] first
-                            */
-                            0x6 => {
-                                println!("Opcode: {:#X} (Right Shift)", opcode);
-                                if !self.modern {
-                                    self.v[x] = self.v[y]
-                                }
-                                
-                                let old = self.v[x];
-                                self.v[x] >>= 1;
-
-                                // Set v[F] to shifted out bit
-                                self.v[0xF] = old & 1;
-                            }
-
-                            // 7: set v[X] = v[Y] - v[X], v[F] = 1 if v[Y] > v[X] else 0
-                            0x7 => {
-                                println!("Opcode: {:#X} (Subtract Y-X)", opcode);
-                                let (res, underflow) = self.v[y].overflowing_sub(self.v[x]);
-                                self.v[x] = res;
-                                self.v[0xF] = if underflow { 0 } else { 1 };
-                            }
-
-                            /*
-                                E: left shift v[X]
-                                modern: ignore Y
-                                original: set v[X] = v[Y] first
-                            */
-                            0xE => {
-                                println!("Opcode: {:#X} (Left Shift)", opcode);
-                                if !self.modern {
-                                    self.v[x] = self.v[y]
-                                }
-
-                                let old = self.v[x];
-                                self.v[x] <<= 1;
-
-                                // Set v[F] to shifted out bit
-                                self.v[0xF] = (old >> 7) & 1;
-                            }
-
-                            _ => println!("Opcode: {:#X} (not implemented)", opcode)
-                        }
-                    }
+            // 2NNN: push to stack and jump
+            Instruction::Call { nnn } => {
+                if self.stack.len() == STACK_SIZE {
+                    return Err(Chip8Error::StackOverflow { pc, opcode });
+                }
+                self.stack.push(self.pc);
+                self.pc = nnn;
+            }
 
-                    // 9XY0: skip next if v[X] != v[Y]
-                    0x9000 => {
-                        println!("Opcode: {:#X} (Skip Reg Not Equal)", opcode);
-                        let r1 = (n2 >> 8) as usize;
-                        let r2 = (n3 >> 4) as usize;
-                        if self.v[r1] != self.v[r2] {
-                            self.pc += 2
-                        }
-                    }
+            // 3XNN: skip next if v[X] = NN
+            Instruction::SkipEqImm { x, nn } => {
+                if self.v[x as usize] == nn {
+                    self.skip();
+                }
+            }
 
-                    // ANNN: set i register to 0x0NNN
-                    0xA000 => {
-                        println!("Opcode: {:#X} (Set i)", opcode);
-                        self.i = n2 | n3 | n4;
-                    }
+            // 4XNN: skip next if v[X] != NN
+            Instruction::SkipNeImm { x, nn } => {
+                if self.v[x as usize] != nn {
+                    self.skip();
+                }
+            }
 
-                    /*
-                        BNNN / BXNN: jump with offset
-                        modern: jump to XNN + v[X]
-                        original: jump to NNN + v[0]
-                    */
-                    0xB000 => {
-                        println!("Opcode: {:#X} (Jump With Offset)", opcode);
-                        if self.modern {
-                            let xnn = n2 | n3 | n4;
-                            let x = (n2 >> 8) as usize;
-                            self.pc = xnn + (self.v[x] as u16);
-                        } else {
-                            let nnn = n2 | n3 | n4;
-                            self.pc = nnn + (self.v[0] as u16);
-                        }
-                    }
+            // 5XY0: skip next if v[X] = v[Y]
+            Instruction::SkipEqReg { x, y } => {
+                if self.v[x as usize] == self.v[y as usize] {
+                    self.skip();
+                }
+            }
 
-                    // CXNN: v[X] = bitwise AND random u8 with NN
-                    0xC000 => {
-                        println!("Opcode: {:#X} (Random)", opcode);
-                        let r: u8 = self.rng.gen_range(0..=255);
-                        let nn = (n3 | n4) as u8;
-                        let x = (n2 >> 8) as usize;
-                        self.v[x] = r & nn;
-                    }
+            // 5XY2: store v[X] to v[Y] in memory at i, i is unchanged
+            Instruction::StoreRange { x, y } => {
+                let regs = register_range(x, y);
+                let range = self.mem_range(AccessKind::Write, self.i as usize, regs.len(), pc, opcode)?;
+                for (addr, &r) in range.zip(regs.iter()) {
+                    self.memory[addr] = self.v[r];
+                }
+            }
 
-                    // DXYN: display
-                    0xD000 => {
-                        println!("Opcode: {:#X} (Display)", opcode);
-                        let x = (n2 >> 8) as usize;
-                        let y = (n3 >> 4) as usize;
-                        let n = n4 as usize;
-                        let vx = self.v[x] as usize;
-                        let vy = self.v[y] as usize;
-                        self.v[0xF] = 0;
-
-                        for row in 0..n {
-                            let spr_byte = self.memory[(self.i as usize) + row];
-
-                            // Iterate bit by bit (MSB to LSB)
-                            for i in (0..=7).rev() {
-                                let bit = (spr_byte >> i) & 1;
-                                if bit == 1 {
-                                    let draw_x = (vx + 7 - i) & 63;
-                                    let draw_y = (vy + row) & 31;
-                                    let display_index = draw_y * 64 + draw_x;
-                                    
-                                    if self.display[display_index] == 1 {
-                                        self.display[display_index] = 0;
-                                        self.v[0xF] = 1;
-                                    } else {
-                                        self.display[display_index] = 1;
-                                    }
-                                }
-                            }
-                        }
+            // 5XY3: load v[X] to v[Y] from memory at i, i is unchanged
+            Instruction::LoadRange { x, y } => {
+                let regs = register_range(x, y);
+                let range = self.mem_range(AccessKind::Read, self.i as usize, regs.len(), pc, opcode)?;
+                for (addr, &r) in range.zip(regs.iter()) {
+                    self.v[r] = self.memory[addr];
+                }
+            }
 
-                        return ExecutionEffect::DisplayUpdate;
-                    }
+            // 6XNN: set register VX to NN
+            Instruction::SetImm { x, nn } => self.v[x as usize] = nn,
 
-                    // E: Key
-                    0xE000 => {
-                        let x = (n2 >> 8) as usize;
-                        let b2 = n3 | n4;
-
-                        match b2 {
-                            // EX9E: skip if keypad[v[X]]
-                            0x9E => {
-                                println!("Opcode: {:#X} (Skip if Key)", opcode);
-                                if self.keypad[self.v[x] as usize] {
-                                    self.pc += 2;
-                                }
-                            }
-
-                            // EXA1: skip if !keypad[v[X]]
-                            0xA1 => {
-                                println!("Opcode: {:#X} (Skip if not Key)", opcode);
-                                if !self.keypad[self.v[x] as usize] {
-                                    self.pc += 2;
-                                }
-                            }
-
-                            _ => println!("Opcode: {:#X} (not implemented)", opcode)
-                        }
+            // 7XNN: add NN to value in VX
+            Instruction::AddImm { x, nn } => {
+                // Prevent overflow panic, wrap instead
+                self.v[x as usize] = self.v[x as usize].wrapping_add(nn);
+            }
+
+            // 8XY0: set v[X] = v[Y]
+            Instruction::SetReg { x, y } => self.v[x as usize] = self.v[y as usize],
+
+            // 8XY1: set v[X] = v[X] | v[Y]
+            Instruction::Or { x, y } => {
+                self.v[x as usize] |= self.v[y as usize];
+                if self.quirks.vf_reset {
+                    self.v[0xF] = 0;
+                }
+            }
+
+            // 8XY2: set v[X] = v[X] & v[Y]
+            Instruction::And { x, y } => {
+                self.v[x as usize] &= self.v[y as usize];
+                if self.quirks.vf_reset {
+                    self.v[0xF] = 0;
+                }
+            }
+
+            // 8XY3: set v[X] = v[X] ^ v[Y]
+            Instruction::Xor { x, y } => {
+                self.v[x as usize] ^= self.v[y as usize];
+                if self.quirks.vf_reset {
+                    self.v[0xF] = 0;
+                }
+            }
+
+            // 8XY4: set v[X] = v[X] + v[Y], set v[F] to carry
+            Instruction::AddReg { x, y } => {
+                let (sum, overflow) = self.v[x as usize].overflowing_add(self.v[y as usize]);
+                self.v[x as usize] = sum;
+                self.v[0xF] = if overflow { 1 } else { 0 };
+            }
+
+            // 8XY5: set v[X] = v[X] - v[Y], v[F] = 1 if v[X] >= v[Y] else 0
+            Instruction::SubXY { x, y } => {
+                let (res, underflow) = self.v[x as usize].overflowing_sub(self.v[y as usize]);
+                self.v[x as usize] = res;
+                self.v[0xF] = if underflow { 0 } else { 1 };
+            }
+
+            /*
+                8XY6: right shift v[X]
+                shift_uses_vy quirk: set v[X] = v[Y] first
+            */
+            Instruction::ShiftRight { x, y } => {
+                if self.quirks.shift_uses_vy {
+                    self.v[x as usize] = self.v[y as usize];
+                }
+
+                let old = self.v[x as usize];
+                self.v[x as usize] >>= 1;
+
+                // Set v[F] to shifted out bit
+                self.v[0xF] = old & 1;
+            }
+
+            // 8XY7: set v[X] = v[Y] - v[X], v[F] = 1 if v[Y] >= v[X] else 0
+            Instruction::SubYX { x, y } => {
+                let (res, underflow) = self.v[y as usize].overflowing_sub(self.v[x as usize]);
+                self.v[x as usize] = res;
+                self.v[0xF] = if underflow { 0 } else { 1 };
+            }
+
+            /*
+                8XYE: left shift v[X]
+                shift_uses_vy quirk: set v[X] = v[Y] first
+            */
+            Instruction::ShiftLeft { x, y } => {
+                if self.quirks.shift_uses_vy {
+                    self.v[x as usize] = self.v[y as usize];
+                }
+
+                let old = self.v[x as usize];
+                self.v[x as usize] <<= 1;
+
+                // Set v[F] to shifted out bit
+                self.v[0xF] = (old >> 7) & 1;
+            }
+
+            // 9XY0: skip next if v[X] != v[Y]
+            Instruction::SkipNeReg { x, y } => {
+                if self.v[x as usize] != self.v[y as usize] {
+                    self.skip();
+                }
+            }
+
+            // ANNN: set i register to 0x0NNN
+            Instruction::SetI { nnn } => self.i = nnn,
+
+            /*
+                BNNN / BXNN: jump with offset
+                jump_uses_vx quirk: jump to XNN + v[X]
+                otherwise: jump to NNN + v[0]
+            */
+            Instruction::JumpOffset { x, nnn } => {
+                let offset = if self.quirks.jump_uses_vx { self.v[x as usize] } else { self.v[0] };
+                self.pc = nnn + offset as u16;
+            }
+
+            // CXNN: v[X] = bitwise AND random u8 with NN
+            Instruction::Random { x, nn } => {
+                let r = match self.random_mode {
+                    RandomMode::Xorshift => self.rng.next_u8(),
+                    RandomMode::CosmacVip => {
+                        let page = &self.memory[VIP_RANDOM_PAGE..VIP_RANDOM_PAGE + 0x100];
+                        self.rng.next_vip(page.try_into().unwrap())
                     }
+                };
+                self.v[x as usize] = r & nn;
+            }
 
-                    // F: Misc.
-                    0xF000 => {
-                        let x = (n2 >> 8) as usize;
-                        let b2 = n3 | n4;
-
-                        match b2 {
-                            // 07: set v[X] to delay timer
-                            0x07 => {
-                                println!("Opcode: {:#X} (Set to Delay Timer)", opcode);
-                                self.v[x] = self.delay_timer;
-                            }
-
-                            // 0A: wait for key, set v[X] to key number on press
-                            0x0A => {
-                                println!("Opcode: {:#X} (Wait For Key)", opcode);
-                                if !self.key_pressed {
-                                    self.pc -= 2;
-                                    return ExecutionEffect::WaitingForKey;
-                                } else {
-                                    self.v[x] = self.pressed_key as u8;
-                                    self.key_pressed = false;
-                                }
-                            }
-
-                            // 15: set delay timer = v[X]
-                            0x15 => {
-                                println!("Opcode: {:#X} (Set Delay Timer)", opcode);
-                                self.delay_timer = self.v[x];
-                            }
-
-                            // 18: set sound timer = v[X]
-                            0x18 => {
-                                println!("Opcode: {:#X} (Set Sound Timer)", opcode);
-                                self.sound_timer = self.v[x];
-                                if self.sound_timer > 0 {
-                                    return ExecutionEffect::Sound;
-                                }
-                            }
-
-                            // 1E: add v[X] to i
-                            0x1E => {
-                                println!("Opcode: {:#X} (Add i)", opcode);
-                                let (res, overflow) = self.i.overflowing_add(self.v[x] as u16);
-                                self.i = res;
-                                self.v[0xF] = if overflow { 1 } else { 0 };
-                            }
-
-                            // 29: set i to location of font character in v[X]
-                            0x29 => {
-                                println!("Opcode: {:#X} (Font Character)", opcode);
-                                self.i = (self.v[x] * 5) as u16;
-                            }
-
-                            // 33: store decimal digits of number in v[X] in memory
-                            0x33 => {
-                                println!("Opcode: {:#X} (Decimal)", opcode);
-                                let h = self.v[x] / 100;
-                                let t = (self.v[x] - h * 100) / 10;
-                                let u = self.v[x] - h * 100 - t * 10;
-
-                                self.memory[self.i as usize] = h;
-                                self.memory[(self.i + 1) as usize] = t;
-                                self.memory[(self.i + 2) as usize] = u;
-                            }
-
-                            /*
-                                55: store registers (v[0] up to v[X]) in memory
-                                modern: leave i unchanged
-                                original: update i to i + X + 1
-                            */
-                            0x55 => {
-                                println!("Opcode: {:#X} (Store Registers)", opcode);
-                                for i in 0..=x {
-                                    let mem_index = (self.i as usize) + i;
-                                    self.memory[mem_index] = self.v[i];
-                                }
-
-                                if !self.modern {
-                                    self.i += (x + 1) as u16;
-                                }
-                            }
-
-                            /*
-                                65: load registers (v[0] up to v[X]) from memory
-                                modern: leave i unchanged
-                                original: update i to i + X + 1
-                            */
-                            0x65 => {
-                                println!("Opcode: {:#X} (Load Registers)", opcode);
-                                for i in 0..=x {
-                                    let mem_index = (self.i as usize) + i;
-                                    self.v[i] = self.memory[mem_index];
-                                }
-
-                                if !self.modern {
-                                    self.i += (x + 1) as u16;
-                                }
-                            }
-
-                            _ => println!("Opcode: {:#X} (not implemented)", opcode)
-                        }
+            // DXYN: display, DXY0 draws a 16x16 sprite
+            Instruction::Draw { x, y, n } => {
+                // Only one draw per frame: retry this instruction until the
+                // next tick, before the sprite is read so watchpoints see one read
+                if self.quirks.display_wait {
+                    if !self.vblank {
+                        self.pc -= 2;
+                        return Ok(ExecutionEffect::NoEffect);
                     }
+                    self.vblank = false;
+                }
+                let sprite_bytes = if n == 0 { 32 } else { n as usize };
+                let len = sprite_bytes * self.plane.count_ones() as usize;
+                self.mem_range(AccessKind::Read, self.i as usize, len, pc, opcode)?;
+                return Ok(self.draw_sprite(x as usize, y as usize, n as usize));
+            }
+
+            // EX9E: skip if keypad[v[X]]
+            Instruction::SkipKey { x } => {
+                if self.keypad[(self.v[x as usize] & 0xF) as usize] {
+                    self.skip();
+                }
+            }
+
+            // EXA1: skip if !keypad[v[X]]
+            Instruction::SkipNotKey { x } => {
+                if !self.keypad[(self.v[x as usize] & 0xF) as usize] {
+                    self.skip();
+                }
+            }
+
+            // F000 NNNN: set i to the 16 bit address in the next word
+            Instruction::SetILong => {
+                let range = self.mem_range(AccessKind::Read, self.pc as usize, 2, pc, opcode)?;
+                let word = &self.memory[range];
+                self.i = ((word[0] as u16) << 8) | word[1] as u16;
+                self.pc = self.pc.checked_add(2).ok_or(Chip8Error::PcOutOfBounds { pc: self.pc })?;
+            }
+
+            // FN01: select the planes to draw to (N is the plane mask, not a register)
+            Instruction::SelectPlane { n } => self.plane = n & 0x3,
+
+            // F002: load the 16 byte audio pattern from memory at i
+            Instruction::AudioPattern => {
+                let range = self.mem_range(AccessKind::Read, self.i as usize, 16, pc, opcode)?;
+                let mut pattern = [0; 16];
+                pattern.copy_from_slice(&self.memory[range]);
+                self.audio_pattern = Some(pattern);
+            }
+
+            // FX07: set v[X] to delay timer
+            Instruction::GetDelay { x } => self.v[x as usize] = self.delay_timer,
+
+            // FX0A: wait for key, set v[X] to key number on press
+            Instruction::WaitKey { x } => {
+                if !self.key_pressed {
+                    self.pc -= 2;
+                    return Ok(ExecutionEffect::WaitingForKey);
+                } else {
+                    self.v[x as usize] = self.pressed_key as u8;
+                    self.key_pressed = false;
+                }
+            }
+
+            // FX15: set delay timer = v[X]
+            Instruction::SetDelay { x } => self.delay_timer = self.v[x as usize],
 
-                    _ => println!("Opcode: {:#X} (not implemented)", opcode)
+            // FX18: set sound timer = v[X]
+            Instruction::SetSound { x } => {
+                self.sound_timer = self.v[x as usize];
+                if self.sound_timer > 0 {
+                    return Ok(ExecutionEffect::Sound);
                 }
             }
+
+            // FX1E: add v[X] to i
+            Instruction::AddI { x } => {
+                let (res, overflow) = self.i.overflowing_add(self.v[x as usize] as u16);
+                self.i = res;
+                self.v[0xF] = if overflow { 1 } else { 0 };
+            }
+
+            // FX29: set i to location of font character in v[X]
+            Instruction::Font { x } => {
+                self.i = (FONT_ADDR + (self.v[x as usize] & 0xF) as usize * 5) as u16;
+            }
+
+            // FX30: set i to location of big font character in v[X]
+            Instruction::BigFont { x } => {
+                self.i = (BIG_FONT_ADDR + (self.v[x as usize] & 0xF) as usize * 10) as u16;
+            }
+
+            // FX33: store decimal digits of number in v[X] in memory
+            Instruction::Bcd { x } => {
+                let val = self.v[x as usize];
+                let h = val / 100;
+                let t = (val - h * 100) / 10;
+                let u = val - h * 100 - t * 10;
+
+                let range = self.mem_range(AccessKind::Write, self.i as usize, 3, pc, opcode)?;
+                self.memory[range].copy_from_slice(&[h, t, u]);
+            }
+
+            // FX3A: set audio pitch = v[X]
+            Instruction::SetPitch { x } => self.pitch = self.v[x as usize],
+
+            /*
+                FX55: store registers (v[0] up to v[X]) in memory
+                memory_increment quirk: leave i unchanged, or add X or X + 1
+            */
+            Instruction::StoreRegs { x } => {
+                let x = x as usize;
+                let range = self.mem_range(AccessKind::Write, self.i as usize, x + 1, pc, opcode)?;
+                self.memory[range].copy_from_slice(&self.v[..=x]);
+
+                self.increment_i_after_transfer(x);
+            }
+
+            /*
+                FX65: load registers (v[0] up to v[X]) from memory
+                memory_increment quirk: leave i unchanged, or add X or X + 1
+            */
+            Instruction::LoadRegs { x } => {
+                let x = x as usize;
+                let range = self.mem_range(AccessKind::Read, self.i as usize, x + 1, pc, opcode)?;
+                self.v[..=x].copy_from_slice(&self.memory[range]);
+
+                self.increment_i_after_transfer(x);
+            }
+
+            // FX75: save v[0] up to v[X] to the RPL user flags
+            Instruction::SaveFlags { x } => {
+                let x = x as usize;
+                self.rpl[..=x].copy_from_slice(&self.v[..=x]);
+            }
+
+            // FX85: load v[0] up to v[X] from the RPL user flags
+            Instruction::LoadFlags { x } => {
+                let x = x as usize;
+                self.v[..=x].copy_from_slice(&self.rpl[..=x]);
+            }
+
+            Instruction::Unknown(_) => return Err(Chip8Error::UnknownOpcode { pc, opcode })
         }
 
-        ExecutionEffect::NoEffect
+        Ok(ExecutionEffect::NoEffect)
     }
-}
\ No newline at end of file
+}
+
+// Registers v[X] to v[Y], in reverse order when X > Y
+fn register_range(x: u8, y: u8) -> Vec<usize> {
+    let (x, y) = (x as usize, y as usize);
+    if x <= y {
+        (x..=y).collect()
+    } else {
+        (y..=x).rev().collect()
+    }
+}
+
+fn memory_size(quirks: &Quirks) -> usize {
+    if quirks.extended_memory {
+        EXTENDED_MEMORY_SIZE
+    } else {
+        MEMORY_SIZE
+    }
+}
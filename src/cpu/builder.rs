@@ -0,0 +1,113 @@
+use crate::quirks::Quirks;
+use crate::rng::RandomMode;
+
+use super::{Chip8, PROGRAM_START, STACK_SIZE};
+
+// Builds a machine in a given state, for tests: registers, memory and stack
+// can be set directly instead of running a program to get there.
+//
+//     let chip8 = Chip8::builder().v(0, 5).i(0x300).program(&[0x80, 0x14]).build();
+//
+// Fonts are loaded as by init. Setters panic on values the machine could not
+// hold, like a register above VF or memory past the end.
+pub struct Chip8Builder {
+    chip8: Chip8
+}
+
+impl Chip8Builder {
+    pub fn new() -> Chip8Builder {
+        let mut chip8 = Chip8::new(Quirks::default());
+        chip8.init();
+        Chip8Builder { chip8 }
+    }
+
+    pub fn quirks(mut self, quirks: Quirks) -> Chip8Builder {
+        self.chip8.set_quirks(quirks);
+        self
+    }
+
+    pub fn v(mut self, x: usize, value: u8) -> Chip8Builder {
+        self.chip8.v[x] = value;
+        self
+    }
+
+    pub fn i(mut self, i: u16) -> Chip8Builder {
+        self.chip8.i = i;
+        self
+    }
+
+    pub fn pc(mut self, pc: u16) -> Chip8Builder {
+        self.chip8.pc = pc;
+        self
+    }
+
+    // Copy bytes into memory at addr
+    pub fn memory(mut self, addr: usize, bytes: &[u8]) -> Chip8Builder {
+        self.chip8.memory[addr..addr + bytes.len()].copy_from_slice(bytes);
+        self
+    }
+
+    // Copy bytes into memory at 0x200, where pc starts
+    pub fn program(self, bytes: &[u8]) -> Chip8Builder {
+        self.memory(PROGRAM_START, bytes)
+    }
+
+    // Return addresses, innermost call last
+    pub fn stack(mut self, stack: &[u16]) -> Chip8Builder {
+        assert!(stack.len() <= STACK_SIZE, "stack holds at most {} addresses", STACK_SIZE);
+        self.chip8.stack = stack.to_vec();
+        self
+    }
+
+    pub fn delay_timer(mut self, value: u8) -> Chip8Builder {
+        self.chip8.delay_timer = value;
+        self
+    }
+
+    pub fn sound_timer(mut self, value: u8) -> Chip8Builder {
+        self.chip8.sound_timer = value;
+        self
+    }
+
+    pub fn key_down(mut self, hex: u8) -> Chip8Builder {
+        self.chip8.set_key(hex, true);
+        self
+    }
+
+    pub fn hires(mut self, hires: bool) -> Chip8Builder {
+        self.chip8.hires = hires;
+        self
+    }
+
+    // XO-CHIP planes to draw to, as selected by FN01
+    pub fn plane(mut self, plane: u8) -> Chip8Builder {
+        self.chip8.plane = plane & 0x3;
+        self
+    }
+
+    pub fn seed(mut self, seed: u64) -> Chip8Builder {
+        self.chip8.set_seed(seed);
+        self
+    }
+
+    pub fn random_mode(mut self, mode: RandomMode) -> Chip8Builder {
+        self.chip8.set_random_mode(mode);
+        self
+    }
+
+    pub fn build(self) -> Chip8 {
+        self.chip8
+    }
+}
+
+impl Default for Chip8Builder {
+    fn default() -> Chip8Builder {
+        Chip8Builder::new()
+    }
+}
+
+impl Chip8 {
+    pub fn builder() -> Chip8Builder {
+        Chip8Builder::new()
+    }
+}
@@ -0,0 +1,64 @@
+use crate::error::Chip8Error;
+
+use super::{Chip8, ExecutionEffect};
+
+// What the instructions of a frame did
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FrameResult {
+    pub cycles: u64, // instructions executed, including one that failed
+    pub display_updated: bool,
+    pub sound: bool, // the sound timer was set
+    pub stopped: bool, // ended early by run_frame_until, the timers did not tick yet
+    pub exited: bool, // 00FD
+    pub error: Option<Chip8Error>
+}
+
+impl FrameResult {
+    // Ran the whole instruction budget and ticked the timers
+    pub fn completed(&self) -> bool {
+        !self.stopped && !self.exited && self.error.is_none()
+    }
+}
+
+impl Chip8 {
+    // One 60 Hz frame: cycles_per_frame instructions, then the timers tick once.
+    // The instruction budget per frame sets the speed, 12 is 720 per second.
+    pub fn run_frame(&mut self, cycles_per_frame: u64) -> FrameResult {
+        self.run_frame_until(cycles_per_frame, |_| false)
+    }
+
+    // Like run_frame, but asks stop before each instruction, for breakpoints.
+    // When it says to stop the frame ends there, and the next call carries on
+    // with the rest of it, so the timers tick at the same rate when stepping.
+    pub fn run_frame_until(&mut self, cycles_per_frame: u64, mut stop: impl FnMut(&Chip8) -> bool) -> FrameResult {
+        let mut result = FrameResult::default();
+
+        while self.frame_cycles < cycles_per_frame {
+            if stop(self) {
+                result.stopped = true;
+                return result;
+            }
+
+            let effect = self.fetch().and_then(|op| self.execute(op));
+            self.frame_cycles += 1;
+            result.cycles += 1;
+            match effect {
+                Ok(ExecutionEffect::DisplayUpdate) => result.display_updated = true,
+                Ok(ExecutionEffect::Sound) => result.sound = true,
+                Ok(ExecutionEffect::Exit) => {
+                    result.exited = true;
+                    return result;
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    result.error = Some(e);
+                    return result;
+                }
+            }
+        }
+
+        self.frame_cycles = 0;
+        self.decrement_timers();
+        result
+    }
+}
@@ -0,0 +1,214 @@
+use crate::crc32::crc32;
+use crate::error::StateError;
+use crate::quirks::{MemoryIncrement, Quirks};
+use crate::rng::{RandomMode, Rng};
+
+use super::{Chip8, DISPLAY_HEIGHT, DISPLAY_WIDTH, EXTENDED_MEMORY_SIZE, MEMORY_SIZE, STACK_SIZE};
+
+/*
+    Save state layout, integers little endian:
+    "C8ST", version: u16, payload length: u32, payload, CRC-32 of payload: u32
+*/
+const MAGIC: &[u8; 4] = b"C8ST";
+const VERSION: u16 = 1;
+const HEADER_LEN: usize = 10;
+
+impl Chip8 {
+    // Snapshot the whole machine
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut w = Vec::new();
+
+        let q = &self.quirks;
+        w.extend_from_slice(&[
+            q.shift_uses_vy as u8,
+            q.jump_uses_vx as u8,
+            match q.memory_increment {
+                MemoryIncrement::None => 0,
+                MemoryIncrement::X => 1,
+                MemoryIncrement::XPlusOne => 2
+            },
+            q.vf_reset as u8,
+            q.clip_sprites as u8,
+            q.display_wait as u8,
+            q.extended_memory as u8
+        ]);
+
+        w.extend_from_slice(&(self.memory.len() as u32).to_le_bytes());
+        w.extend_from_slice(&self.memory);
+        w.extend_from_slice(&self.display);
+        w.push(self.hires as u8);
+        w.push(self.plane);
+
+        w.extend_from_slice(&self.pc.to_le_bytes());
+        w.extend_from_slice(&self.i.to_le_bytes());
+        w.push(self.stack.len() as u8);
+        for addr in &self.stack {
+            w.extend_from_slice(&addr.to_le_bytes());
+        }
+        w.push(self.delay_timer);
+        w.push(self.sound_timer);
+        w.extend_from_slice(&self.v);
+        w.push(self.vblank as u8);
+        w.extend_from_slice(&self.rng.state().to_le_bytes());
+        w.push(match self.random_mode {
+            RandomMode::Xorshift => 0,
+            RandomMode::CosmacVip => 1
+        });
+
+        for &key in &self.keypad {
+            w.push(key as u8);
+        }
+        w.push(self.key_pressed as u8);
+        w.push(self.pressed_key as u8);
+
+        w.extend_from_slice(&self.rpl);
+        match &self.audio_pattern {
+            Some(pattern) => {
+                w.push(1);
+                w.extend_from_slice(pattern);
+            }
+            None => w.push(0)
+        }
+        w.push(self.pitch);
+
+        let mut state = Vec::with_capacity(HEADER_LEN + w.len() + 4);
+        state.extend_from_slice(MAGIC);
+        state.extend_from_slice(&VERSION.to_le_bytes());
+        state.extend_from_slice(&(w.len() as u32).to_le_bytes());
+        state.extend_from_slice(&w);
+        state.extend_from_slice(&crc32(&w).to_le_bytes());
+        state
+    }
+
+    // Restore a snapshot from save_state. The machine is unchanged on error.
+    pub fn load_state(&mut self, state: &[u8]) -> Result<(), StateError> {
+        if state.len() < HEADER_LEN || &state[..4] != MAGIC {
+            return Err(StateError::BadMagic);
+        }
+        let version = u16::from_le_bytes([state[4], state[5]]);
+        if version != VERSION {
+            return Err(StateError::UnsupportedVersion(version));
+        }
+        let len = u32::from_le_bytes([state[6], state[7], state[8], state[9]]) as usize;
+        if state.len() < HEADER_LEN + len + 4 {
+            return Err(StateError::Truncated);
+        }
+        let payload = &state[HEADER_LEN..HEADER_LEN + len];
+        let crc = &state[HEADER_LEN + len..HEADER_LEN + len + 4];
+        if crc32(payload) != u32::from_le_bytes([crc[0], crc[1], crc[2], crc[3]]) {
+            return Err(StateError::ChecksumMismatch);
+        }
+
+        // Decode into a fresh machine, then swap it in
+        let mut r = Reader { data: payload, pos: 0 };
+        let quirks = Quirks {
+            shift_uses_vy: r.bool()?,
+            jump_uses_vx: r.bool()?,
+            memory_increment: match r.u8()? {
+                0 => MemoryIncrement::None,
+                1 => MemoryIncrement::X,
+                2 => MemoryIncrement::XPlusOne,
+                _ => return Err(StateError::Invalid("memory increment quirk"))
+            },
+            vf_reset: r.bool()?,
+            clip_sprites: r.bool()?,
+            display_wait: r.bool()?,
+            extended_memory: r.bool()?
+        };
+
+        let mut chip8 = Chip8::new(quirks);
+
+        let memory_len = r.u32()? as usize;
+        if memory_len != if quirks.extended_memory { EXTENDED_MEMORY_SIZE } else { MEMORY_SIZE } {
+            return Err(StateError::Invalid("memory size"));
+        }
+        chip8.memory = r.bytes(memory_len)?.to_vec();
+        chip8.display.copy_from_slice(r.bytes(DISPLAY_WIDTH * DISPLAY_HEIGHT)?);
+        chip8.hires = r.bool()?;
+        chip8.plane = r.u8()? & 0x3;
+
+        chip8.pc = r.u16()?;
+        chip8.i = r.u16()?;
+        let depth = r.u8()? as usize;
+        if depth > STACK_SIZE {
+            return Err(StateError::Invalid("stack depth"));
+        }
+        for _ in 0..depth {
+            chip8.stack.push(r.u16()?);
+        }
+        chip8.delay_timer = r.u8()?;
+        chip8.sound_timer = r.u8()?;
+        chip8.v.copy_from_slice(r.bytes(16)?);
+        chip8.vblank = r.bool()?;
+        let rng_state = r.u64()?;
+        chip8.random_mode = match r.u8()? {
+            0 => RandomMode::Xorshift,
+            1 => RandomMode::CosmacVip,
+            _ => return Err(StateError::Invalid("random mode"))
+        };
+        chip8.rng = Rng::from_state(rng_state, chip8.random_mode).ok_or(StateError::Invalid("random state"))?;
+
+        for key in chip8.keypad.iter_mut() {
+            *key = r.bool()?;
+        }
+        chip8.key_pressed = r.bool()?;
+        chip8.pressed_key = (r.u8()? & 0xF) as usize;
+
+        chip8.rpl.copy_from_slice(r.bytes(16)?);
+        if r.bool()? {
+            let mut pattern = [0; 16];
+            pattern.copy_from_slice(r.bytes(16)?);
+            chip8.audio_pattern = Some(pattern);
+        }
+        chip8.pitch = r.u8()?;
+
+        // Debugging aids are not part of the machine. Undo history belongs
+        // to the old timeline, so recording carries on from a clean log.
+        chip8.access_log = self.access_log.take();
+        chip8.undo = self.undo.take();
+        chip8.tracer = self.tracer.take();
+        if let Some(undo) = &mut chip8.undo {
+            undo.clear();
+        }
+
+        *self = chip8;
+        Ok(())
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], StateError> {
+        let bytes = self.data.get(self.pos..self.pos + len).ok_or(StateError::Truncated)?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, StateError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn bool(&mut self) -> Result<bool, StateError> {
+        Ok(self.u8()? != 0)
+    }
+
+    fn u16(&mut self) -> Result<u16, StateError> {
+        let b = self.bytes(2)?;
+        Ok(u16::from_le_bytes([b[0], b[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, StateError> {
+        let b = self.bytes(4)?;
+        Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn u64(&mut self) -> Result<u64, StateError> {
+        let mut b = [0; 8];
+        b.copy_from_slice(self.bytes(8)?);
+        Ok(u64::from_le_bytes(b))
+    }
+}
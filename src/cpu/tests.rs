@@ -0,0 +1,1042 @@
+use super::*;
+use crate::error::StateError;
+use crate::trace::TraceLevel;
+
+// Put opcode at pc, then fetch and execute it
+fn run(chip8: &mut Chip8, opcode: u16) -> ExecutionEffect {
+    try_run(chip8, opcode).unwrap()
+}
+
+fn try_run(chip8: &mut Chip8, opcode: u16) -> Result<ExecutionEffect, Chip8Error> {
+    let pc = chip8.pc as usize;
+    chip8.memory[pc..pc + 2].copy_from_slice(&opcode.to_be_bytes());
+    let opcode = chip8.fetch()?;
+    chip8.execute(opcode)
+}
+
+fn lit(chip8: &Chip8) -> usize {
+    chip8.display().iter().filter(|&&px| px != 0).count()
+}
+
+// 00E0 - 00FF
+
+#[test]
+fn clear_blanks_the_selected_planes() {
+    let mut chip8 = Chip8::builder().plane(1).build();
+    chip8.display[0] = 3;
+    chip8.display[1] = 1;
+    run(&mut chip8, 0x00E0);
+    assert_eq!(&chip8.display[..2], &[2, 0]);
+}
+
+#[test]
+fn return_pops_the_stack() {
+    let mut chip8 = Chip8::builder().stack(&[0x300, 0x400]).build();
+    run(&mut chip8, 0x00EE);
+    assert_eq!(chip8.pc, 0x400);
+    assert_eq!(chip8.stack(), &[0x300]);
+}
+
+#[test]
+fn return_with_empty_stack_underflows() {
+    let mut chip8 = Chip8::builder().build();
+    let err = try_run(&mut chip8, 0x00EE).unwrap_err();
+    assert_eq!(err, Chip8Error::StackUnderflow { pc: 0x200, opcode: 0x00EE });
+}
+
+#[test]
+fn scroll_down_moves_pixels_down() {
+    let mut chip8 = Chip8::builder().build();
+    chip8.display[0] = 1;
+    assert_eq!(run(&mut chip8, 0x00C3), ExecutionEffect::DisplayUpdate);
+    assert_eq!(chip8.display()[0], 0);
+    assert_eq!(chip8.display()[3 * 64], 1);
+}
+
+#[test]
+fn scroll_right_and_left_move_four_pixels() {
+    let mut chip8 = Chip8::builder().build();
+    chip8.display[10] = 1;
+    run(&mut chip8, 0x00FB);
+    assert_eq!(chip8.display()[14], 1);
+    run(&mut chip8, 0x00FC);
+    run(&mut chip8, 0x00FC);
+    assert_eq!(chip8.display()[6], 1);
+    assert_eq!(lit(&chip8), 1);
+}
+
+#[test]
+fn scroll_drops_pixels_off_the_edge() {
+    let mut chip8 = Chip8::builder().build();
+    chip8.display[62] = 1;
+    run(&mut chip8, 0x00FB);
+    assert_eq!(lit(&chip8), 0);
+}
+
+#[test]
+fn exit_stays_on_the_instruction() {
+    let mut chip8 = Chip8::builder().build();
+    assert_eq!(run(&mut chip8, 0x00FD), ExecutionEffect::Exit);
+    assert_eq!(chip8.pc, 0x200);
+}
+
+#[test]
+fn resolution_switches_clear_the_screen() {
+    let mut chip8 = Chip8::builder().build();
+    chip8.display[0] = 1;
+    run(&mut chip8, 0x00FF);
+    assert_eq!(chip8.resolution(), (128, 64));
+    assert_eq!(lit(&chip8), 0);
+
+    chip8.display[0] = 1;
+    run(&mut chip8, 0x00FE);
+    assert_eq!(chip8.resolution(), (64, 32));
+    assert_eq!(lit(&chip8), 0);
+}
+
+// 1NNN - 2NNN
+
+#[test]
+fn jump_sets_pc() {
+    let mut chip8 = Chip8::builder().build();
+    assert_eq!(run(&mut chip8, 0x1345), ExecutionEffect::NoEffect);
+    assert_eq!(chip8.pc, 0x345);
+}
+
+#[test]
+fn jump_to_self_is_reported() {
+    let mut chip8 = Chip8::builder().build();
+    assert_eq!(run(&mut chip8, 0x1200), ExecutionEffect::JumpToSelf);
+    assert_eq!(chip8.pc, 0x200);
+}
+
+#[test]
+fn call_pushes_the_return_address() {
+    let mut chip8 = Chip8::builder().build();
+    run(&mut chip8, 0x2345);
+    assert_eq!(chip8.pc, 0x345);
+    assert_eq!(chip8.stack(), &[0x202]);
+}
+
+#[test]
+fn call_with_full_stack_overflows() {
+    let mut chip8 = Chip8::builder().stack(&[0x300; STACK_SIZE]).build();
+    let err = try_run(&mut chip8, 0x2345).unwrap_err();
+    assert_eq!(err, Chip8Error::StackOverflow { pc: 0x200, opcode: 0x2345 });
+    assert_eq!(chip8.stack().len(), STACK_SIZE);
+}
+
+// 3XNN - 5XY3, 9XY0
+
+#[test]
+fn skip_if_equal_immediate() {
+    let mut chip8 = Chip8::builder().v(3, 0x42).build();
+    run(&mut chip8, 0x3342);
+    assert_eq!(chip8.pc, 0x204);
+
+    let mut chip8 = Chip8::builder().v(3, 0x41).build();
+    run(&mut chip8, 0x3342);
+    assert_eq!(chip8.pc, 0x202);
+}
+
+#[test]
+fn skip_if_not_equal_immediate() {
+    let mut chip8 = Chip8::builder().v(3, 0x41).build();
+    run(&mut chip8, 0x4342);
+    assert_eq!(chip8.pc, 0x204);
+
+    let mut chip8 = Chip8::builder().v(3, 0x42).build();
+    run(&mut chip8, 0x4342);
+    assert_eq!(chip8.pc, 0x202);
+}
+
+#[test]
+fn skip_if_registers_equal() {
+    let mut chip8 = Chip8::builder().v(1, 7).v(2, 7).build();
+    run(&mut chip8, 0x5120);
+    assert_eq!(chip8.pc, 0x204);
+
+    let mut chip8 = Chip8::builder().v(1, 7).v(2, 8).build();
+    run(&mut chip8, 0x5120);
+    assert_eq!(chip8.pc, 0x202);
+}
+
+#[test]
+fn skip_if_registers_not_equal() {
+    let mut chip8 = Chip8::builder().v(1, 7).v(2, 8).build();
+    run(&mut chip8, 0x9120);
+    assert_eq!(chip8.pc, 0x204);
+
+    let mut chip8 = Chip8::builder().v(1, 7).v(2, 7).build();
+    run(&mut chip8, 0x9120);
+    assert_eq!(chip8.pc, 0x202);
+}
+
+#[test]
+fn skip_jumps_over_a_long_load() {
+    let mut chip8 = Chip8::builder().program(&[0x30, 0x00, 0xF0, 0x00, 0x12, 0x34]).build();
+    let opcode = chip8.fetch().unwrap();
+    chip8.execute(opcode).unwrap();
+    assert_eq!(chip8.pc, 0x206);
+}
+
+#[test]
+fn fetch_at_the_end_of_64k_is_out_of_bounds() {
+    let mut chip8 = Chip8::builder().quirks(Quirks::XO_CHIP).pc(0xFFFE).build();
+    assert_eq!(chip8.fetch(), Err(Chip8Error::PcOutOfBounds { pc: 0xFFFE }));
+    assert_eq!(chip8.pc, 0xFFFE);
+}
+
+#[test]
+fn long_load_at_the_end_of_64k_is_out_of_bounds() {
+    let mut chip8 = Chip8::builder().quirks(Quirks::XO_CHIP).pc(0xFFFC).memory(0xFFFC, &[0xF0, 0x00, 0x12, 0x34]).build();
+    let frame = chip8.run_frame(1);
+    assert_eq!(frame.error, Some(Chip8Error::PcOutOfBounds { pc: 0xFFFE }));
+}
+
+#[test]
+fn store_range_writes_registers_in_order() {
+    let mut chip8 = Chip8::builder().v(1, 0xA).v(2, 0xB).v(3, 0xC).i(0x300).build();
+    run(&mut chip8, 0x5132);
+    assert_eq!(&chip8.memory[0x300..0x303], &[0xA, 0xB, 0xC]);
+    assert_eq!(chip8.i, 0x300);
+}
+
+#[test]
+fn store_range_reversed_when_x_above_y() {
+    let mut chip8 = Chip8::builder().v(1, 0xA).v(2, 0xB).v(3, 0xC).i(0x300).build();
+    run(&mut chip8, 0x5312);
+    assert_eq!(&chip8.memory[0x300..0x303], &[0xC, 0xB, 0xA]);
+}
+
+#[test]
+fn load_range_reads_registers() {
+    let mut chip8 = Chip8::builder().memory(0x300, &[1, 2, 3]).i(0x300).build();
+    run(&mut chip8, 0x5243);
+    assert_eq!(&chip8.v[2..5], &[1, 2, 3]);
+    assert_eq!(chip8.i, 0x300);
+}
+
+// 6XNN - 7XNN
+
+#[test]
+fn set_immediate() {
+    let mut chip8 = Chip8::builder().build();
+    run(&mut chip8, 0x6A42);
+    assert_eq!(chip8.v[0xA], 0x42);
+}
+
+#[test]
+fn add_immediate_wraps_without_touching_vf() {
+    let mut chip8 = Chip8::builder().v(1, 0xFF).v(0xF, 5).build();
+    run(&mut chip8, 0x7102);
+    assert_eq!(chip8.v[1], 0x01);
+    assert_eq!(chip8.v[0xF], 5);
+}
+
+// 8XY0 - 8XYE
+
+#[test]
+fn set_register() {
+    let mut chip8 = Chip8::builder().v(2, 9).build();
+    run(&mut chip8, 0x8120);
+    assert_eq!(chip8.v[1], 9);
+}
+
+#[test]
+fn logic_ops_reset_vf_with_quirk() {
+    for (opcode, result) in [(0x8121, 0b1110), (0x8122, 0b1000), (0x8123, 0b0110)] {
+        let mut chip8 = Chip8::builder().quirks(Quirks::COSMAC_VIP).v(1, 0b1100).v(2, 0b1010).v(0xF, 7).build();
+        run(&mut chip8, opcode);
+        assert_eq!(chip8.v[1], result, "{:04X}", opcode);
+        assert_eq!(chip8.v[0xF], 0, "{:04X}", opcode);
+    }
+}
+
+#[test]
+fn logic_ops_keep_vf_without_quirk() {
+    for (opcode, result) in [(0x8121, 0b1110), (0x8122, 0b1000), (0x8123, 0b0110)] {
+        let mut chip8 = Chip8::builder().quirks(Quirks::SUPER_CHIP_1_1).v(1, 0b1100).v(2, 0b1010).v(0xF, 7).build();
+        run(&mut chip8, opcode);
+        assert_eq!(chip8.v[1], result, "{:04X}", opcode);
+        assert_eq!(chip8.v[0xF], 7, "{:04X}", opcode);
+    }
+}
+
+#[test]
+fn add_sets_carry() {
+    let mut chip8 = Chip8::builder().v(1, 0xF0).v(2, 0x20).build();
+    run(&mut chip8, 0x8124);
+    assert_eq!(chip8.v[1], 0x10);
+    assert_eq!(chip8.v[0xF], 1);
+}
+
+#[test]
+fn add_clears_carry() {
+    let mut chip8 = Chip8::builder().v(1, 0x10).v(2, 0x20).v(0xF, 1).build();
+    run(&mut chip8, 0x8124);
+    assert_eq!(chip8.v[1], 0x30);
+    assert_eq!(chip8.v[0xF], 0);
+}
+
+#[test]
+fn add_into_vf_keeps_the_flag() {
+    let mut chip8 = Chip8::builder().v(0xF, 0xFF).v(1, 2).build();
+    run(&mut chip8, 0x8F14);
+    assert_eq!(chip8.v[0xF], 1);
+
+    let mut chip8 = Chip8::builder().v(0xF, 1).v(1, 2).build();
+    run(&mut chip8, 0x8F14);
+    assert_eq!(chip8.v[0xF], 0);
+}
+
+#[test]
+fn add_uses_vf_as_operand_before_setting_the_flag() {
+    let mut chip8 = Chip8::builder().v(1, 0xFF).v(0xF, 1).build();
+    run(&mut chip8, 0x81F4);
+    assert_eq!(chip8.v[1], 0);
+    assert_eq!(chip8.v[0xF], 1);
+}
+
+#[test]
+fn sub_sets_not_borrow() {
+    let mut chip8 = Chip8::builder().v(1, 0x30).v(2, 0x10).build();
+    run(&mut chip8, 0x8125);
+    assert_eq!(chip8.v[1], 0x20);
+    assert_eq!(chip8.v[0xF], 1);
+}
+
+#[test]
+fn sub_clears_flag_on_borrow() {
+    let mut chip8 = Chip8::builder().v(1, 0x10).v(2, 0x20).v(0xF, 1).build();
+    run(&mut chip8, 0x8125);
+    assert_eq!(chip8.v[1], 0xF0);
+    assert_eq!(chip8.v[0xF], 0);
+}
+
+#[test]
+fn sub_of_equal_values_sets_flag() {
+    let mut chip8 = Chip8::builder().v(1, 0x10).v(2, 0x10).build();
+    run(&mut chip8, 0x8125);
+    assert_eq!(chip8.v[1], 0);
+    assert_eq!(chip8.v[0xF], 1);
+}
+
+#[test]
+fn sub_into_vf_keeps_the_flag() {
+    let mut chip8 = Chip8::builder().v(0xF, 0x10).v(1, 0x20).build();
+    run(&mut chip8, 0x8F15);
+    assert_eq!(chip8.v[0xF], 0);
+}
+
+#[test]
+fn reverse_sub_sets_not_borrow() {
+    let mut chip8 = Chip8::builder().v(1, 0x10).v(2, 0x30).build();
+    run(&mut chip8, 0x8127);
+    assert_eq!(chip8.v[1], 0x20);
+    assert_eq!(chip8.v[0xF], 1);
+}
+
+#[test]
+fn reverse_sub_clears_flag_on_borrow() {
+    let mut chip8 = Chip8::builder().v(1, 0x30).v(2, 0x10).v(0xF, 1).build();
+    run(&mut chip8, 0x8127);
+    assert_eq!(chip8.v[1], 0xE0);
+    assert_eq!(chip8.v[0xF], 0);
+}
+
+#[test]
+fn reverse_sub_into_vf_keeps_the_flag() {
+    let mut chip8 = Chip8::builder().v(0xF, 0x10).v(1, 0x20).build();
+    run(&mut chip8, 0x8F17);
+    assert_eq!(chip8.v[0xF], 1);
+}
+
+#[test]
+fn shift_right_uses_vy_with_quirk() {
+    let mut chip8 = Chip8::builder().quirks(Quirks::COSMAC_VIP).v(1, 0x80).v(2, 0x03).build();
+    run(&mut chip8, 0x8126);
+    assert_eq!(chip8.v[1], 0x01);
+    assert_eq!(chip8.v[0xF], 1);
+}
+
+#[test]
+fn shift_right_uses_vx_without_quirk() {
+    let mut chip8 = Chip8::builder().quirks(Quirks::SUPER_CHIP_1_1).v(1, 0x80).v(2, 0x03).build();
+    run(&mut chip8, 0x8126);
+    assert_eq!(chip8.v[1], 0x40);
+    assert_eq!(chip8.v[0xF], 0);
+}
+
+#[test]
+fn shift_left_uses_vy_with_quirk() {
+    let mut chip8 = Chip8::builder().quirks(Quirks::COSMAC_VIP).v(1, 0x01).v(2, 0x81).build();
+    run(&mut chip8, 0x812E);
+    assert_eq!(chip8.v[1], 0x02);
+    assert_eq!(chip8.v[0xF], 1);
+}
+
+#[test]
+fn shift_left_uses_vx_without_quirk() {
+    let mut chip8 = Chip8::builder().quirks(Quirks::SUPER_CHIP_1_1).v(1, 0x01).v(2, 0x81).build();
+    run(&mut chip8, 0x812E);
+    assert_eq!(chip8.v[1], 0x02);
+    assert_eq!(chip8.v[0xF], 0);
+}
+
+#[test]
+fn shift_into_vf_keeps_the_flag() {
+    let mut chip8 = Chip8::builder().quirks(Quirks::SUPER_CHIP_1_1).v(0xF, 0x02).build();
+    run(&mut chip8, 0x8F06);
+    assert_eq!(chip8.v[0xF], 0);
+
+    let mut chip8 = Chip8::builder().quirks(Quirks::SUPER_CHIP_1_1).v(0xF, 0x80).build();
+    run(&mut chip8, 0x8F0E);
+    assert_eq!(chip8.v[0xF], 1);
+}
+
+// ANNN - CXNN
+
+#[test]
+fn set_i() {
+    let mut chip8 = Chip8::builder().build();
+    run(&mut chip8, 0xA123);
+    assert_eq!(chip8.i, 0x123);
+}
+
+#[test]
+fn jump_offset_uses_v0() {
+    let mut chip8 = Chip8::builder().quirks(Quirks::COSMAC_VIP).v(0, 4).v(3, 8).build();
+    run(&mut chip8, 0xB300);
+    assert_eq!(chip8.pc, 0x304);
+}
+
+#[test]
+fn jump_offset_uses_vx_with_quirk() {
+    let mut chip8 = Chip8::builder().quirks(Quirks::SUPER_CHIP_1_1).v(0, 4).v(3, 8).build();
+    run(&mut chip8, 0xB300);
+    assert_eq!(chip8.pc, 0x308);
+}
+
+#[test]
+fn random_is_masked() {
+    let mut chip8 = Chip8::builder().seed(1).build();
+    let mut expected = Rng::new(1);
+    run(&mut chip8, 0xC10F);
+    assert_eq!(chip8.v[1], expected.next_u8() & 0x0F);
+    run(&mut chip8, 0xC100);
+    assert_eq!(chip8.v[1], 0);
+}
+
+#[test]
+fn random_repeats_with_the_same_seed() {
+    for mode in [RandomMode::Xorshift, RandomMode::CosmacVip] {
+        let mut a = Chip8::builder().seed(42).random_mode(mode).build();
+        let mut b = Chip8::builder().seed(42).random_mode(mode).build();
+        for _ in 0..100 {
+            run(&mut a, 0xC1FF);
+            run(&mut b, 0xC1FF);
+            assert_eq!(a.v[1], b.v[1]);
+            a.pc = 0x200;
+            b.pc = 0x200;
+        }
+    }
+}
+
+#[test]
+fn vip_random_follows_the_interpreter_routine() {
+    // R9 = 0x1234: the last result 0x12, the next byte read at 0x35
+    let mut chip8 = Chip8::builder().seed(0x1234).random_mode(RandomMode::CosmacVip).build();
+    // 0x90 + 0x12 = 0xA2, 0x51 + 0xA2 = 0xF3
+    run(&mut chip8, 0xC1FF);
+    assert_eq!(chip8.v[1], 0xF3);
+    assert_eq!(chip8.rng.state() & 0xFFFF, 0xF335);
+
+    // The interrupt counts R9 up too: 0xE0 + 0xF3 = 0xD3 carry 1, 0xE9 + 0xD3 = 0xBC
+    chip8.decrement_timers();
+    chip8.pc = 0x200;
+    run(&mut chip8, 0xC10F);
+    assert_eq!(chip8.v[1], 0x0C);
+    assert_eq!(chip8.rng.state() & 0xFFFF, 0xBC37);
+}
+
+// DXYN
+
+#[test]
+fn draw_xors_and_reports_collision() {
+    let mut chip8 = Chip8::builder().quirks(Quirks::SUPER_CHIP_1_1).memory(0x300, &[0xF0]).i(0x300).v(1, 2).v(2, 3).build();
+    assert_eq!(run(&mut chip8, 0xD121), ExecutionEffect::DisplayUpdate);
+    assert_eq!(&chip8.display()[3 * 64 + 2..3 * 64 + 7], &[1, 1, 1, 1, 0]);
+    assert_eq!(chip8.v[0xF], 0);
+
+    chip8.pc = 0x200;
+    run(&mut chip8, 0xD121);
+    assert_eq!(lit(&chip8), 0);
+    assert_eq!(chip8.v[0xF], 1);
+}
+
+#[test]
+fn draw_wraps_the_start_position() {
+    let mut chip8 = Chip8::builder().quirks(Quirks::SUPER_CHIP_1_1).memory(0x300, &[0x80]).i(0x300).v(1, 66).v(2, 33).build();
+    run(&mut chip8, 0xD121);
+    assert_eq!(chip8.display()[64 + 2], 1);
+}
+
+#[test]
+fn draw_clips_at_the_edge_with_quirk() {
+    let mut chip8 = Chip8::builder().quirks(Quirks::SUPER_CHIP_1_1).memory(0x300, &[0xFF]).i(0x300).v(1, 60).build();
+    run(&mut chip8, 0xD121);
+    assert_eq!(lit(&chip8), 4);
+}
+
+#[test]
+fn draw_wraps_at_the_edge_without_quirk() {
+    let mut chip8 = Chip8::builder().quirks(Quirks::XO_CHIP).memory(0x300, &[0xFF]).i(0x300).v(1, 60).build();
+    run(&mut chip8, 0xD121);
+    assert_eq!(lit(&chip8), 8);
+    assert_eq!(chip8.display()[0], 1);
+}
+
+#[test]
+fn draw_waits_for_vblank_with_quirk() {
+    let mut chip8 = Chip8::builder().quirks(Quirks::COSMAC_VIP).memory(0x300, &[0x80]).i(0x300).build();
+    run(&mut chip8, 0xD011);
+    assert_eq!(lit(&chip8), 1);
+
+    // The second draw in the same frame retries
+    run(&mut chip8, 0xD011);
+    assert_eq!(chip8.pc, 0x202);
+    assert_eq!(lit(&chip8), 1);
+
+    chip8.decrement_timers();
+    run(&mut chip8, 0xD011);
+    assert_eq!(chip8.pc, 0x204);
+    assert_eq!(lit(&chip8), 0);
+}
+
+#[test]
+fn draw_retries_dont_read_the_sprite() {
+    let mut chip8 = Chip8::builder().quirks(Quirks::COSMAC_VIP).memory(0x300, &[0x80]).i(0x300).build();
+    chip8.set_access_logging(true);
+    let sprite_read = MemoryAccess { kind: AccessKind::Read, start: 0x300, len: 1 };
+    run(&mut chip8, 0xD011);
+    assert!(chip8.accesses().contains(&sprite_read));
+
+    // Waiting for vblank only fetches the instruction
+    run(&mut chip8, 0xD011);
+    assert!(!chip8.accesses().contains(&sprite_read));
+
+    chip8.decrement_timers();
+    run(&mut chip8, 0xD011);
+    assert!(chip8.accesses().contains(&sprite_read));
+}
+
+#[test]
+fn draw_16x16_sprite() {
+    let mut chip8 = Chip8::builder().quirks(Quirks::SUPER_CHIP_1_1).hires(true).memory(0x300, &[0xFF; 32]).i(0x300).build();
+    run(&mut chip8, 0xD010);
+    assert_eq!(lit(&chip8), 256);
+}
+
+#[test]
+fn draw_to_both_planes_reads_two_sprites() {
+    let mut chip8 = Chip8::builder().quirks(Quirks::XO_CHIP).plane(3).memory(0x300, &[0x80, 0xC0]).i(0x300).build();
+    run(&mut chip8, 0xD011);
+    assert_eq!(&chip8.display()[..2], &[3, 2]);
+}
+
+#[test]
+fn draw_past_memory_fails() {
+    let mut chip8 = Chip8::builder().i(0xFFE).build();
+    let err = try_run(&mut chip8, 0xD015).unwrap_err();
+    assert_eq!(err, Chip8Error::MemoryOutOfBounds { pc: 0x200, opcode: 0xD015, address: 0x1002 });
+}
+
+// EX9E - EXA1
+
+#[test]
+fn skip_if_key_pressed() {
+    let mut chip8 = Chip8::builder().v(1, 0xA).key_down(0xA).build();
+    run(&mut chip8, 0xE19E);
+    assert_eq!(chip8.pc, 0x204);
+
+    let mut chip8 = Chip8::builder().v(1, 0xA).build();
+    run(&mut chip8, 0xE19E);
+    assert_eq!(chip8.pc, 0x202);
+}
+
+#[test]
+fn skip_if_key_not_pressed() {
+    let mut chip8 = Chip8::builder().v(1, 0xA).build();
+    run(&mut chip8, 0xE1A1);
+    assert_eq!(chip8.pc, 0x204);
+
+    let mut chip8 = Chip8::builder().v(1, 0xA).key_down(0xA).build();
+    run(&mut chip8, 0xE1A1);
+    assert_eq!(chip8.pc, 0x202);
+}
+
+// F000 - FX85
+
+#[test]
+fn long_load_reads_the_next_word() {
+    let mut chip8 = Chip8::builder().quirks(Quirks::XO_CHIP).program(&[0xF0, 0x00, 0xAB, 0xCD]).build();
+    let opcode = chip8.fetch().unwrap();
+    chip8.execute(opcode).unwrap();
+    assert_eq!(chip8.i, 0xABCD);
+    assert_eq!(chip8.pc, 0x204);
+}
+
+#[test]
+fn select_plane() {
+    let mut chip8 = Chip8::builder().build();
+    run(&mut chip8, 0xF201);
+    assert_eq!(chip8.plane, 2);
+}
+
+#[test]
+fn audio_pattern_is_loaded_from_i() {
+    let pattern: Vec<u8> = (0..16).collect();
+    let mut chip8 = Chip8::builder().memory(0x300, &pattern).i(0x300).build();
+    run(&mut chip8, 0xF002);
+    assert_eq!(chip8.audio_pattern().unwrap()[..], pattern[..]);
+}
+
+#[test]
+fn get_and_set_delay_timer() {
+    let mut chip8 = Chip8::builder().delay_timer(30).v(2, 9).build();
+    run(&mut chip8, 0xF107);
+    assert_eq!(chip8.v[1], 30);
+    run(&mut chip8, 0xF215);
+    assert_eq!(chip8.delay_timer(), 9);
+}
+
+#[test]
+fn wait_key_repeats_until_a_key_is_pressed() {
+    let mut chip8 = Chip8::builder().build();
+    assert_eq!(run(&mut chip8, 0xF30A), ExecutionEffect::WaitingForKey);
+    assert_eq!(chip8.pc, 0x200);
+
+    chip8.set_key(0x7, true);
+    assert_eq!(run(&mut chip8, 0xF30A), ExecutionEffect::NoEffect);
+    assert_eq!(chip8.v[3], 0x7);
+    assert_eq!(chip8.pc, 0x202);
+}
+
+#[test]
+fn set_key_holds_until_released() {
+    let mut chip8 = Chip8::builder().build();
+    chip8.set_key(0xC, true);
+    assert!(chip8.key(0xC));
+    chip8.set_key(0xC, false);
+    assert!(!chip8.key(0xC));
+}
+
+#[test]
+fn set_key_ignores_keys_off_the_keypad() {
+    let mut chip8 = Chip8::builder().build();
+    chip8.set_key(0x10, true);
+    chip8.set_key(0xFF, true);
+    assert!(!chip8.key(0x10));
+    assert!((0..16).all(|hex| !chip8.key(hex)));
+    // FX0A is still waiting
+    assert_eq!(run(&mut chip8, 0xF30A), ExecutionEffect::WaitingForKey);
+}
+
+#[test]
+fn set_sound_timer_starts_sound() {
+    let mut chip8 = Chip8::builder().v(1, 10).build();
+    assert_eq!(run(&mut chip8, 0xF118), ExecutionEffect::Sound);
+    assert_eq!(chip8.sound_timer(), 10);
+
+    assert_eq!(run(&mut chip8, 0xF018), ExecutionEffect::NoEffect);
+    assert_eq!(chip8.sound_timer(), 0);
+}
+
+#[test]
+fn add_to_i() {
+    let mut chip8 = Chip8::builder().i(0x300).v(1, 0x10).v(0xF, 1).build();
+    run(&mut chip8, 0xF11E);
+    assert_eq!(chip8.i, 0x310);
+    assert_eq!(chip8.v[0xF], 0);
+}
+
+#[test]
+fn add_to_i_sets_vf_on_overflow() {
+    let mut chip8 = Chip8::builder().i(0xFFFF).v(1, 0x02).build();
+    run(&mut chip8, 0xF11E);
+    assert_eq!(chip8.i, 0x0001);
+    assert_eq!(chip8.v[0xF], 1);
+}
+
+#[test]
+fn font_characters() {
+    let mut chip8 = Chip8::builder().v(1, 0xA).build();
+    run(&mut chip8, 0xF129);
+    assert_eq!(chip8.i as usize, FONT_ADDR + 0xA * 5);
+    assert_eq!(&chip8.memory[chip8.i as usize..chip8.i as usize + 5], &FONT[50..55]);
+
+    run(&mut chip8, 0xF130);
+    assert_eq!(chip8.i as usize, BIG_FONT_ADDR + 0xA * 10);
+    assert_eq!(&chip8.memory[chip8.i as usize..chip8.i as usize + 10], &BIG_FONT[100..110]);
+}
+
+#[test]
+fn bcd_stores_decimal_digits() {
+    let mut chip8 = Chip8::builder().v(1, 254).i(0x300).build();
+    run(&mut chip8, 0xF133);
+    assert_eq!(&chip8.memory[0x300..0x303], &[2, 5, 4]);
+    assert_eq!(chip8.i, 0x300);
+}
+
+#[test]
+fn set_pitch() {
+    let mut chip8 = Chip8::builder().v(1, 112).build();
+    run(&mut chip8, 0xF13A);
+    assert_eq!(chip8.playback_rate(), 8000.0);
+}
+
+#[test]
+fn store_and_load_registers_increment_i_by_quirk() {
+    let cases = [
+        (Quirks::SUPER_CHIP_1_1, 0x300),
+        (Quirks::CHIP_48, 0x302),
+        (Quirks::COSMAC_VIP, 0x303)
+    ];
+    for (quirks, i) in cases {
+        let mut chip8 = Chip8::builder().quirks(quirks).v(0, 1).v(1, 2).v(2, 3).i(0x300).build();
+        run(&mut chip8, 0xF255);
+        assert_eq!(&chip8.memory[0x300..0x304], &[1, 2, 3, 0]);
+        assert_eq!(chip8.i, i);
+
+        chip8.i = 0x300;
+        chip8.v = [0; 16];
+        run(&mut chip8, 0xF265);
+        assert_eq!(&chip8.v[..4], &[1, 2, 3, 0]);
+        assert_eq!(chip8.i, i);
+    }
+}
+
+#[test]
+fn store_registers_past_memory_fails() {
+    let mut chip8 = Chip8::builder().i(0xFFF).build();
+    let err = try_run(&mut chip8, 0xF155).unwrap_err();
+    assert_eq!(err, Chip8Error::MemoryOutOfBounds { pc: 0x200, opcode: 0xF155, address: 0x1000 });
+}
+
+#[test]
+fn save_and_load_flags() {
+    let mut chip8 = Chip8::builder().v(0, 4).v(1, 5).v(2, 6).build();
+    run(&mut chip8, 0xF175);
+    chip8.v = [0; 16];
+    run(&mut chip8, 0xF285);
+    assert_eq!(&chip8.v[..3], &[4, 5, 0]);
+}
+
+#[test]
+fn unknown_opcode_fails() {
+    let mut chip8 = Chip8::builder().build();
+    let err = try_run(&mut chip8, 0xE1FF).unwrap_err();
+    assert_eq!(err, Chip8Error::UnknownOpcode { pc: 0x200, opcode: 0xE1FF });
+}
+
+// Tracing
+
+// A trace sink the test can read back
+#[derive(Clone, Default)]
+struct SharedBuffer(std::rc::Rc<std::cell::RefCell<Vec<u8>>>);
+
+impl std::io::Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl SharedBuffer {
+    fn text(&self) -> String {
+        String::from_utf8(self.0.borrow().clone()).unwrap()
+    }
+}
+
+fn traced(level: TraceLevel, configure: impl FnOnce(&mut Tracer)) -> (Chip8, SharedBuffer) {
+    let buffer = SharedBuffer::default();
+    let mut tracer = Tracer::new(level, Box::new(buffer.clone()));
+    configure(&mut tracer);
+    let mut chip8 = Chip8::builder().v(0xF, 0xAB).i(0x123).build();
+    chip8.set_tracer(Some(tracer));
+    (chip8, buffer)
+}
+
+#[test]
+fn trace_writes_state_before_each_instruction() {
+    let (mut chip8, buffer) = traced(TraceLevel::Instructions, |_| {});
+    run(&mut chip8, 0x6A01);
+    assert_eq!(buffer.text(), "0200 6A01 LD VA, 0x01            V 000000000000000000000000000000AB I 0123\n");
+}
+
+#[test]
+fn trace_filters_by_address_and_class() {
+    let (mut chip8, buffer) = traced(TraceLevel::Instructions, |tracer| {
+        tracer.set_addresses(Some(0x202..=0x2FF));
+        tracer.set_classes(1 << 0x6);
+    });
+    run(&mut chip8, 0x6001); // before the range
+    run(&mut chip8, 0x7001); // other class
+    run(&mut chip8, 0x6102);
+    let text = buffer.text();
+    assert_eq!(text.lines().count(), 1);
+    assert!(text.starts_with("0204 6102"));
+}
+
+#[test]
+fn trace_events_level_skips_instructions() {
+    let (mut chip8, buffer) = traced(TraceLevel::Events, |_| {});
+    run(&mut chip8, 0x6001);
+    chip8.set_key(0xA, true);
+    chip8.set_key(0xA, false);
+    assert_eq!(buffer.text(), "key down A\nkey up A\n");
+}
+
+// Frames
+
+#[test]
+fn run_frame_executes_the_budget_then_ticks_timers() {
+    // 7001 in a loop: ADD V0, 1 then JP 0x200
+    let mut chip8 = Chip8::builder().program(&[0x70, 0x01, 0x12, 0x00]).delay_timer(5).build();
+    let result = chip8.run_frame(10);
+    assert!(result.completed());
+    assert_eq!(result.cycles, 10);
+    assert_eq!(chip8.v[0], 5);
+    assert_eq!(chip8.delay_timer, 4);
+}
+
+#[test]
+fn run_frame_until_stops_without_ticking_timers() {
+    let mut chip8 = Chip8::builder().program(&[0x70, 0x01, 0x12, 0x00]).delay_timer(5).build();
+    let result = chip8.run_frame_until(10, |chip8| chip8.v[0] == 2);
+    assert!(result.stopped);
+    assert_eq!(result.cycles, 3);
+    assert_eq!(chip8.delay_timer, 5);
+}
+
+#[test]
+fn stepping_ticks_timers_once_per_frame_budget() {
+    // One instruction per call, as when single-stepping in the debugger
+    let mut chip8 = Chip8::builder().program(&[0x70, 0x01, 0x12, 0x00]).delay_timer(5).build();
+    for step in 1..=10 {
+        let mut ran = false;
+        let result = chip8.run_frame_until(4, |_| std::mem::replace(&mut ran, true));
+        assert_eq!(result.cycles, 1);
+        assert_eq!(result.completed(), step % 4 == 0);
+    }
+    // 10 instructions at 4 per frame
+    assert_eq!(chip8.delay_timer, 3);
+}
+
+#[test]
+fn run_frame_reports_display_and_exit() {
+    let mut chip8 = Chip8::builder().program(&[0xD0, 0x01, 0x00, 0xFD]).build();
+    let result = chip8.run_frame(10);
+    assert!(result.display_updated && result.exited);
+    assert_eq!(result.cycles, 2);
+}
+
+// Save states
+
+// A machine with something in every part of the state
+fn busy_machine() -> Chip8 {
+    let mut chip8 = Chip8::builder()
+        .quirks(Quirks::XO_CHIP)
+        .v(3, 0x42)
+        .i(0x345)
+        .pc(0x280)
+        .stack(&[0x204, 0x2F0])
+        .delay_timer(9)
+        .sound_timer(4)
+        .key_down(0xA)
+        .hires(true)
+        .plane(2)
+        .memory(0x300, &[1, 2, 3])
+        .seed(7)
+        .build();
+    chip8.display[100] = 3;
+    chip8.rpl[5] = 0x55;
+    chip8.audio_pattern = Some([0xF0; 16]);
+    chip8.pitch = 100;
+    chip8
+}
+
+#[test]
+fn save_state_round_trips() {
+    let mut chip8 = busy_machine();
+    let state = chip8.save_state();
+
+    let mut loaded = Chip8::new(Quirks::COSMAC_VIP);
+    loaded.load_state(&state).unwrap();
+    assert_eq!(loaded.save_state(), state);
+    assert_eq!(loaded.quirks, Quirks::XO_CHIP);
+    assert_eq!(loaded.memory, chip8.memory);
+    assert_eq!(loaded.display, chip8.display);
+    assert_eq!((loaded.pc, loaded.i, loaded.v), (chip8.pc, chip8.i, chip8.v));
+    assert_eq!(loaded.stack, chip8.stack);
+    assert_eq!((loaded.delay_timer, loaded.sound_timer), (9, 4));
+    assert_eq!(loaded.keypad, chip8.keypad);
+    assert_eq!((loaded.hires, loaded.plane), (true, 2));
+    assert_eq!((loaded.rpl, loaded.audio_pattern, loaded.pitch), (chip8.rpl, chip8.audio_pattern, 100));
+    // The random numbers carry on where they were
+    assert_eq!(loaded.rng, chip8.rng);
+    assert_eq!(loaded.rng.next_u8(), chip8.rng.next_u8());
+}
+
+// A save state file around a payload
+fn state_file(version: u16, payload: &[u8]) -> Vec<u8> {
+    let mut state = b"C8ST".to_vec();
+    state.extend_from_slice(&version.to_le_bytes());
+    state.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    state.extend_from_slice(payload);
+    state.extend_from_slice(&crate::crc32::crc32(payload).to_le_bytes());
+    state
+}
+
+#[test]
+fn save_states_keep_the_random_mode() {
+    let chip8 = Chip8::builder().seed(5).random_mode(RandomMode::CosmacVip).build();
+    let state = chip8.save_state();
+    let mut loaded = Chip8::builder().build();
+    loaded.load_state(&state).unwrap();
+    assert_eq!(loaded.random_mode(), RandomMode::CosmacVip);
+    assert_eq!(loaded.save_state(), state);
+
+    // The mode byte follows the generator state and is followed by the
+    // keypad, key, RPL flags, audio pattern and pitch
+    let payload = &state[STATE_HEADER_LEN..state.len() - 4];
+    let mode_at = payload.len() - (1 + 16 + 2 + 16 + 1 + 1);
+    let with_random = |rng: u64, mode: u8| {
+        let mut payload = payload.to_vec();
+        payload[mode_at - 8..mode_at].copy_from_slice(&rng.to_le_bytes());
+        payload[mode_at] = mode;
+        state_file(1, &payload)
+    };
+    // The VIP's counter can be 0, xorshift's state can't
+    assert_eq!(loaded.load_state(&with_random(0, 1)), Ok(()));
+    assert_eq!(loaded.load_state(&with_random(0, 0)), Err(StateError::Invalid("random state")));
+    assert_eq!(loaded.load_state(&with_random(5, 2)), Err(StateError::Invalid("random mode")));
+}
+
+// "C8ST", version and payload length
+const STATE_HEADER_LEN: usize = 10;
+
+#[test]
+fn load_state_rejects_bad_files() {
+    let state = busy_machine().save_state();
+    let mut chip8 = Chip8::builder().build();
+
+    let mut bad_magic = state.clone();
+    bad_magic[0] = b'X';
+    assert_eq!(chip8.load_state(&bad_magic), Err(StateError::BadMagic));
+    assert_eq!(chip8.load_state(b"C8"), Err(StateError::BadMagic));
+
+    let mut future = state.clone();
+    future[4..6].copy_from_slice(&99u16.to_le_bytes());
+    assert_eq!(chip8.load_state(&future), Err(StateError::UnsupportedVersion(99)));
+
+    assert_eq!(chip8.load_state(&state[..state.len() - 1]), Err(StateError::Truncated));
+    assert_eq!(chip8.load_state(&state[..STATE_HEADER_LEN + 10]), Err(StateError::Truncated));
+
+    let mut corrupt = state.clone();
+    corrupt[STATE_HEADER_LEN + 20] ^= 1;
+    assert_eq!(chip8.load_state(&corrupt), Err(StateError::ChecksumMismatch));
+
+    // Nothing was loaded
+    assert_eq!(chip8.pc, 0x200);
+    assert_eq!(chip8.v[3], 0);
+}
+
+#[test]
+fn load_state_rejects_memory_the_quirks_dont_allow() {
+    // The extended memory quirk is the 7th payload byte
+    let with_extended_memory = |chip8: &Chip8, extended: bool| {
+        let state = chip8.save_state();
+        let mut payload = state[STATE_HEADER_LEN..state.len() - 4].to_vec();
+        payload[6] = extended as u8;
+        state_file(1, &payload)
+    };
+    let mut chip8 = Chip8::builder().build();
+
+    // 64 KiB without XO-CHIP memory, and 4 KiB with it
+    let xo_chip = busy_machine();
+    assert_eq!(chip8.load_state(&with_extended_memory(&xo_chip, false)), Err(StateError::Invalid("memory size")));
+    let vip = Chip8::builder().quirks(Quirks::COSMAC_VIP).build();
+    assert_eq!(chip8.load_state(&with_extended_memory(&vip, true)), Err(StateError::Invalid("memory size")));
+    assert_eq!(chip8.load_state(&with_extended_memory(&vip, false)), Ok(()));
+}
+
+// Undo
+
+#[test]
+fn step_back_undoes_registers_memory_stack_and_display() {
+    let program = [
+        0x60, 0x05, // v0 := 5
+        0xA3, 0x00, // i := 0x300
+        0xF0, 0x55, // save v0, i becomes 0x301
+        0x22, 0x0A, // call 0x20A
+        0x00, 0x00,
+        0xA2, 0x00, // i := 0x200
+        0xD0, 0x01, // sprite v0 v0 1
+        0x71, 0x01 // v1 += 1
+    ];
+    let mut chip8 = Chip8::builder().quirks(Quirks::COSMAC_VIP).program(&program).memory(0x300, &[0xEE]).build();
+    chip8.set_undo_recording(100);
+    let before = chip8.save_state();
+
+    let result = chip8.run_frame(7);
+    assert!(result.completed());
+    assert_eq!(chip8.undo_depth(), 7);
+    assert_eq!(chip8.memory[0x300], 5);
+    assert_eq!(chip8.stack(), &[0x208]);
+    assert_eq!(lit(&chip8), 2);
+    assert_eq!(chip8.v[1], 1);
+
+    // Back over the sprite and the add
+    assert!(chip8.step_back() && chip8.step_back());
+    assert_eq!(chip8.pc, 0x20C);
+    assert_eq!(chip8.v[1], 0);
+    assert_eq!(lit(&chip8), 0);
+    assert_eq!(chip8.i, 0x200);
+
+    // Back over the call, then the save
+    assert!(chip8.step_back() && chip8.step_back());
+    assert_eq!(chip8.pc, 0x206);
+    assert!(chip8.stack().is_empty());
+    assert!(chip8.step_back());
+    assert_eq!(chip8.memory[0x300], 0xEE);
+    assert_eq!(chip8.i, 0x300);
+
+    assert!(chip8.step_back() && chip8.step_back());
+    assert!(!chip8.step_back());
+    assert_eq!(chip8.save_state(), before);
+}
+
+#[test]
+fn step_back_over_a_frame_boundary_restores_the_timers() {
+    let mut chip8 = Chip8::builder().program(&[0x70, 0x01, 0x12, 0x00]).delay_timer(5).build();
+    chip8.set_undo_recording(100);
+    chip8.run_frame(2);
+    chip8.run_frame(2);
+    assert_eq!(chip8.delay_timer, 3);
+
+    chip8.step_back();
+    assert_eq!(chip8.delay_timer, 4);
+    chip8.step_back();
+    chip8.step_back();
+    assert_eq!(chip8.delay_timer, 5);
+    // The frame carries on from the undone instruction: one more, then a tick
+    let result = chip8.run_frame(2);
+    assert_eq!(result.cycles, 1);
+    assert_eq!(chip8.delay_timer, 4);
+}
@@ -0,0 +1,181 @@
+use std::collections::VecDeque;
+
+use crate::instruction::{decode, Instruction};
+use crate::rng::Rng;
+
+use super::{Chip8, DISPLAY_HEIGHT, DISPLAY_WIDTH};
+
+// Undo log for reverse execution. Each entry holds what one instruction
+// overwrote: the registers, and the old value of every memory byte and pixel
+// it changed. Entries are newest last.
+pub(super) struct UndoLog {
+    entries: VecDeque<UndoEntry>,
+    capacity: usize,
+    writes: Vec<(usize, u8)> // memory overwritten by the instruction being executed
+}
+
+// Machine state from before an instruction
+struct UndoEntry {
+    pc: u16,
+    i: u16,
+    v: [u8; 16],
+    stack: Vec<u16>,
+    delay_timer: u8,
+    sound_timer: u8,
+    plane: u8,
+    hires: bool,
+    vblank: bool,
+    rng: Rng,
+    key_pressed: bool,
+    pressed_key: usize,
+    rpl: [u8; 16],
+    audio_pattern: Option<[u8; 16]>,
+    pitch: u8,
+    frame_cycles: u64,
+    memory: Vec<(usize, u8)>, // address and old value, in write order
+    display: Vec<(usize, u8)> // pixel index and old value
+}
+
+// An entry being filled in while its instruction executes
+pub(super) struct PendingUndo {
+    entry: UndoEntry,
+    display: Option<Box<[u8; DISPLAY_WIDTH * DISPLAY_HEIGHT]>> // copy of the display, for instructions that draw
+}
+
+impl UndoLog {
+    fn new(capacity: usize) -> UndoLog {
+        UndoLog {
+            entries: VecDeque::new(),
+            capacity,
+            writes: Vec::new()
+        }
+    }
+
+    pub(super) fn clear(&mut self) {
+        self.entries.clear();
+        self.writes.clear();
+    }
+}
+
+impl Chip8 {
+    // Record an undo entry for each of the last `capacity` instructions
+    // executed, for step_back. 0 turns recording off.
+    pub fn set_undo_recording(&mut self, capacity: usize) {
+        self.undo = if capacity > 0 { Some(UndoLog::new(capacity)) } else { None };
+    }
+
+    // Number of instructions that can be stepped back
+    pub fn undo_depth(&self) -> usize {
+        self.undo.as_ref().map_or(0, |undo| undo.entries.len())
+    }
+
+    // Undo the last executed instruction, returning to the state before its
+    // fetch. Returns false when the undo log is empty.
+    pub fn step_back(&mut self) -> bool {
+        let Some(entry) = self.undo.as_mut().and_then(|undo| undo.entries.pop_back()) else {
+            return false;
+        };
+
+        // Undo writes newest first, so a byte written twice gets its original value
+        for &(addr, old) in entry.memory.iter().rev() {
+            if let Some(byte) = self.memory.get_mut(addr) {
+                *byte = old;
+            }
+        }
+        for &(index, old) in &entry.display {
+            self.display[index] = old;
+        }
+
+        self.pc = entry.pc;
+        self.i = entry.i;
+        self.v = entry.v;
+        self.stack = entry.stack;
+        self.delay_timer = entry.delay_timer;
+        self.sound_timer = entry.sound_timer;
+        self.plane = entry.plane;
+        self.hires = entry.hires;
+        self.vblank = entry.vblank;
+        self.rng = entry.rng;
+        self.key_pressed = entry.key_pressed;
+        self.pressed_key = entry.pressed_key;
+        self.rpl = entry.rpl;
+        self.audio_pattern = entry.audio_pattern;
+        self.pitch = entry.pitch;
+        self.frame_cycles = entry.frame_cycles;
+
+        // Nothing was accessed going backwards
+        if let Some(log) = &mut self.access_log {
+            log.clear();
+        }
+        true
+    }
+
+    // Capture the registers before opcode executes. The instruction was
+    // fetched already, so it starts 2 bytes before pc.
+    pub(super) fn begin_undo(&self, opcode: u16) -> PendingUndo {
+        let draws = matches!(
+            decode(opcode),
+            Instruction::Clear
+                | Instruction::ScrollDown { .. }
+                | Instruction::ScrollRight
+                | Instruction::ScrollLeft
+                | Instruction::LowRes
+                | Instruction::HighRes
+                | Instruction::Draw { .. }
+        );
+
+        PendingUndo {
+            entry: UndoEntry {
+                pc: self.pc.wrapping_sub(2),
+                i: self.i,
+                v: self.v,
+                stack: self.stack.clone(),
+                delay_timer: self.delay_timer,
+                sound_timer: self.sound_timer,
+                plane: self.plane,
+                hires: self.hires,
+                vblank: self.vblank,
+                rng: self.rng,
+                key_pressed: self.key_pressed,
+                pressed_key: self.pressed_key,
+                rpl: self.rpl,
+                audio_pattern: self.audio_pattern,
+                pitch: self.pitch,
+                frame_cycles: self.frame_cycles,
+                memory: Vec::new(),
+                display: Vec::new()
+            },
+            display: if draws { Some(Box::new(self.display)) } else { None }
+        }
+    }
+
+    // Remember the old value of memory an instruction is about to write
+    pub(super) fn record_write(&mut self, start: usize, len: usize) {
+        if let Some(undo) = &mut self.undo {
+            undo.writes.extend((start..start + len).map(|addr| (addr, self.memory[addr])));
+        }
+    }
+
+    // Finish the entry once the instruction has executed
+    pub(super) fn end_undo(&mut self, pending: PendingUndo) {
+        let PendingUndo { mut entry, display } = pending;
+
+        if let Some(before) = display {
+            entry.display = before
+                .iter()
+                .zip(self.display.iter())
+                .enumerate()
+                .filter(|(_, (old, new))| old != new)
+                .map(|(index, (&old, _))| (index, old))
+                .collect();
+        }
+
+        if let Some(undo) = &mut self.undo {
+            entry.memory = std::mem::take(&mut undo.writes);
+            undo.entries.push_back(entry);
+            if undo.entries.len() > undo.capacity {
+                undo.entries.pop_front();
+            }
+        }
+    }
+}
@@ -0,0 +1,525 @@
+use std::collections::BTreeSet;
+
+use crate::cpu::{AccessKind, Chip8};
+use crate::instruction::{decode, Instruction};
+
+// Step debugger: decides before each instruction whether execution should stop.
+// The frontend feeds it commands and calls should_break before every fetch.
+
+// Which memory accesses a memory watchpoint stops on
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WatchAccess {
+    Read,
+    Write,
+    Any
+}
+
+// Machine state a register watchpoint observes
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WatchTarget {
+    V(u8),
+    I,
+    DelayTimer,
+    SoundTimer,
+    StackDepth
+}
+
+// Register watchpoints stop when the value changes and the new value matches
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WatchCondition {
+    Changed,
+    Equal(u16),
+    NotEqual(u16),
+    Less(u16),
+    Greater(u16)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Watch {
+    Memory { start: u16, end: u16, access: WatchAccess }, // inclusive range
+    Register { target: WatchTarget, condition: WatchCondition }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Command {
+    Break(u16), // add a breakpoint
+    Delete(u16), // remove a breakpoint
+    Breakpoints, // list breakpoints
+    Watch(Watch), // add a watchpoint
+    Unwatch(usize), // remove a watchpoint by number
+    Watches, // list watchpoints
+    Step, // execute one instruction
+    Next, // step over a 2NNN call
+    Finish, // step out to the matching 00EE
+    Until(u16), // run to an address (run to cursor)
+    Continue,
+    StepBack(usize), // undo instructions
+    ReverseContinue(Option<u16>), // run backwards to an address, or to a breakpoint
+    Registers,
+    List, // disassemble around pc
+    Help
+}
+
+pub const HELP: &str = "\
+b, break <addr>     add a breakpoint
+d, delete <addr>    remove a breakpoint
+bl, breakpoints     list breakpoints
+w, watch <addr>[-<end>] [r|w|rw]
+                    stop when memory is read and / or written (default w)
+w, watch <reg> [== | != | < | > <value>]
+                    stop when v0 - vf, i, dt, st or sp (stack depth) changes,
+                    optionally only to a matching value
+wd, unwatch <n>     remove a watchpoint
+wl, watches         list watchpoints
+s, step             execute one instruction
+n, next             step over a subroutine call
+f, finish           run until the current subroutine returns
+u, until <addr>     run to an address
+c, continue         run until a breakpoint
+sb, back [n]        step back n instructions (default 1)
+rc, rcontinue [addr]
+                    run backwards to the last time addr executed,
+                    or to the last breakpoint
+r, regs             show registers
+l, list             disassemble around pc
+h, help             show this help";
+
+pub fn parse_command(line: &str) -> Result<Command, String> {
+    let mut words = line.split_whitespace();
+    let name = words.next().unwrap_or("");
+    let arg = words.next();
+
+    let address = || match arg {
+        Some(text) => parse_address(text).ok_or(format!("invalid address '{}'", text)),
+        None => Err(format!("'{}' needs an address", name))
+    };
+
+    match name {
+        "b" | "break" => Ok(Command::Break(address()?)),
+        "d" | "delete" => Ok(Command::Delete(address()?)),
+        "bl" | "breakpoints" => Ok(Command::Breakpoints),
+        "w" | "watch" => {
+            let rest: Vec<&str> = line.split_whitespace().skip(1).collect();
+            Ok(Command::Watch(parse_watch(&rest)?))
+        }
+        "wd" | "unwatch" => match arg.and_then(|a| a.parse().ok()) {
+            Some(n) => Ok(Command::Unwatch(n)),
+            None => Err(format!("'{}' needs a watchpoint number", name))
+        },
+        "wl" | "watches" => Ok(Command::Watches),
+        "s" | "step" => Ok(Command::Step),
+        "n" | "next" => Ok(Command::Next),
+        "f" | "finish" => Ok(Command::Finish),
+        "u" | "until" => Ok(Command::Until(address()?)),
+        "c" | "continue" => Ok(Command::Continue),
+        "sb" | "back" => match arg {
+            Some(text) => match text.parse() {
+                Ok(n) => Ok(Command::StepBack(n)),
+                Err(_) => Err(format!("invalid count '{}'", text))
+            },
+            None => Ok(Command::StepBack(1))
+        },
+        "rc" | "rcontinue" => match arg {
+            Some(_) => Ok(Command::ReverseContinue(Some(address()?))),
+            None => Ok(Command::ReverseContinue(None))
+        },
+        "r" | "regs" => Ok(Command::Registers),
+        "l" | "list" => Ok(Command::List),
+        "h" | "help" => Ok(Command::Help),
+        "" => Err("empty command".to_string()),
+        other => Err(format!("unknown command '{}', try 'help'", other))
+    }
+}
+
+fn parse_watch(words: &[&str]) -> Result<Watch, String> {
+    let what = match words.first() {
+        Some(what) => what.to_lowercase(),
+        None => return Err("'watch' needs an address or register".to_string())
+    };
+
+    let target = match what.as_str() {
+        "i" => Some(WatchTarget::I),
+        "dt" => Some(WatchTarget::DelayTimer),
+        "st" => Some(WatchTarget::SoundTimer),
+        "sp" => Some(WatchTarget::StackDepth),
+        reg if reg.len() == 2 && reg.starts_with('v') => u8::from_str_radix(&reg[1..], 16).ok().map(WatchTarget::V),
+        _ => None
+    };
+
+    if let Some(target) = target {
+        let condition = match words[1..] {
+            [] => WatchCondition::Changed,
+            [op, value] => {
+                let value = parse_value(value).ok_or(format!("invalid value '{}'", value))?;
+                match op {
+                    "==" => WatchCondition::Equal(value),
+                    "!=" => WatchCondition::NotEqual(value),
+                    "<" => WatchCondition::Less(value),
+                    ">" => WatchCondition::Greater(value),
+                    _ => return Err(format!("unknown condition '{}'", op))
+                }
+            }
+            _ => return Err("expected a condition like '== 5'".to_string())
+        };
+        return Ok(Watch::Register { target, condition });
+    }
+
+    let (start, end) = match what.split_once('-') {
+        Some((start, end)) => (parse_address(start), parse_address(end)),
+        None => (parse_address(&what), parse_address(&what))
+    };
+    let (start, end) = match (start, end) {
+        (Some(start), Some(end)) if start <= end => (start, end),
+        _ => return Err(format!("invalid address range '{}'", what))
+    };
+    let access = match words.get(1).copied() {
+        None | Some("w") => WatchAccess::Write,
+        Some("r") => WatchAccess::Read,
+        Some("rw") => WatchAccess::Any,
+        Some(other) => return Err(format!("unknown access '{}', use r, w or rw", other))
+    };
+    Ok(Watch::Memory { start, end, access })
+}
+
+// Decimal, or hex with 0x
+fn parse_value(text: &str) -> Option<u16> {
+    match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => u16::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok()
+    }
+}
+
+// Hex, with or without 0x
+fn parse_address(text: &str) -> Option<u16> {
+    let digits = text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")).unwrap_or(text);
+    u16::from_str_radix(digits, 16).ok()
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Mode {
+    Paused,
+    Running,
+    Step,
+    StepOver { depth: usize }, // until the stack is back at depth
+    StepOut { depth: usize }, // until the stack is shallower than depth
+    RunTo(u16)
+}
+
+pub struct Debugger {
+    breakpoints: BTreeSet<u16>,
+    watches: Vec<(Watch, Option<u16>)>, // watchpoints and the last value of watched registers
+    mode: Mode,
+    resumed: bool, // the first instruction after resuming never breaks
+    reason: Option<String> // why execution last stopped, if not a plain step
+}
+
+impl Debugger {
+    // Starts paused, before the first instruction
+    pub fn new() -> Debugger {
+        Debugger {
+            breakpoints: BTreeSet::new(),
+            watches: Vec::new(),
+            mode: Mode::Paused,
+            resumed: false,
+            reason: None
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.mode == Mode::Paused
+    }
+
+    pub fn pause(&mut self) {
+        self.mode = Mode::Paused;
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = &u16> {
+        self.breakpoints.iter()
+    }
+
+    // Why execution last stopped, for a breakpoint or watchpoint
+    pub fn take_reason(&mut self) -> Option<String> {
+        self.reason.take()
+    }
+
+    // Apply a command, returning text to show to the user
+    pub fn command(&mut self, command: Command, chip8: &mut Chip8) -> String {
+        match command {
+            Command::Break(addr) => {
+                self.breakpoints.insert(addr);
+                format!("Breakpoint at {:#05X}", addr)
+            }
+            Command::Delete(addr) => {
+                if self.breakpoints.remove(&addr) {
+                    format!("Deleted breakpoint at {:#05X}", addr)
+                } else {
+                    format!("No breakpoint at {:#05X}", addr)
+                }
+            }
+            Command::Breakpoints => {
+                if self.breakpoints.is_empty() {
+                    "No breakpoints".to_string()
+                } else {
+                    let list: Vec<String> = self.breakpoints.iter().map(|a| format!("{:#05X}", a)).collect();
+                    list.join("\n")
+                }
+            }
+            Command::Watch(watch) => {
+                let value = watch_value(&watch, chip8);
+                self.watches.push((watch, value));
+                format!("Watchpoint {}: {}", self.watches.len() - 1, describe_watch(&watch))
+            }
+            Command::Unwatch(n) => {
+                if n < self.watches.len() {
+                    self.watches.remove(n);
+                    format!("Deleted watchpoint {}", n)
+                } else {
+                    format!("No watchpoint {}", n)
+                }
+            }
+            Command::Watches => {
+                if self.watches.is_empty() {
+                    "No watchpoints".to_string()
+                } else {
+                    let list: Vec<String> =
+                        self.watches.iter().enumerate().map(|(n, (w, _))| format!("{}: {}", n, describe_watch(w))).collect();
+                    list.join("\n")
+                }
+            }
+            Command::Step => self.resume(Mode::Step),
+            Command::Next => {
+                // Only a call is stepped over, anything else is a single step
+                let depth = chip8.stack().len();
+                match current_instruction(chip8) {
+                    Some(Instruction::Call { .. }) => self.resume(Mode::StepOver { depth }),
+                    _ => self.resume(Mode::Step)
+                }
+            }
+            Command::Finish => {
+                let depth = chip8.stack().len();
+                if depth == 0 {
+                    return "Not in a subroutine".to_string();
+                }
+                self.resume(Mode::StepOut { depth })
+            }
+            Command::Until(addr) => self.resume(Mode::RunTo(addr)),
+            Command::Continue => self.resume(Mode::Running),
+            Command::StepBack(count) => {
+                self.mode = Mode::Paused;
+                let steps = (0..count).take_while(|_| chip8.step_back()).count();
+                self.update_watches(chip8);
+                if steps == 0 {
+                    return "Nothing to step back to".to_string();
+                }
+                format_listing(chip8, 1)
+            }
+            Command::ReverseContinue(target) => {
+                self.mode = Mode::Paused;
+                let mut reason = "Reached the oldest recorded instruction".to_string();
+                while chip8.step_back() {
+                    let pc = chip8.pc();
+                    let hit = match target {
+                        Some(addr) => pc == addr,
+                        None => self.breakpoints.contains(&pc)
+                    };
+                    if hit {
+                        reason = format!("Back at {:#05X}", pc);
+                        break;
+                    }
+                }
+                self.update_watches(chip8);
+                format!("{}\n{}", reason, format_listing(chip8, 1))
+            }
+            Command::Registers => format_registers(chip8),
+            Command::List => format_listing(chip8, 5),
+            Command::Help => HELP.to_string()
+        }
+    }
+
+    fn resume(&mut self, mode: Mode) -> String {
+        self.mode = mode;
+        self.resumed = true;
+        String::new()
+    }
+
+    // Called before each instruction. Returns true, and pauses, if execution
+    // should stop before the instruction at pc.
+    pub fn should_break(&mut self, chip8: &Chip8) -> bool {
+        if self.mode == Mode::Paused {
+            return true;
+        }
+
+        // Watchpoints look at what the previous instruction did
+        let watch_hit = self.check_watches(chip8);
+        if self.resumed {
+            self.resumed = false;
+            return false;
+        }
+
+        let pc = chip8.pc();
+        let depth = chip8.stack().len();
+        let reason = if self.breakpoints.contains(&pc) {
+            Some(format!("Breakpoint at {:#05X}", pc))
+        } else {
+            watch_hit
+        };
+
+        let stop = reason.is_some()
+            || match self.mode {
+                Mode::Paused => true,
+                Mode::Running => false,
+                Mode::Step => true,
+                Mode::StepOver { depth: target } => depth <= target,
+                Mode::StepOut { depth: target } => depth < target,
+                Mode::RunTo(addr) => pc == addr
+            };
+
+        if stop {
+            self.mode = Mode::Paused;
+            self.reason = reason;
+        }
+        stop
+    }
+}
+
+impl Debugger {
+    // Take the current values of watched registers without triggering,
+    // after the machine moved backwards
+    fn update_watches(&mut self, chip8: &Chip8) {
+        for (watch, last) in self.watches.iter_mut() {
+            *last = watch_value(watch, chip8);
+        }
+    }
+
+    // Update watched values, describing the first watchpoint that triggered
+    fn check_watches(&mut self, chip8: &Chip8) -> Option<String> {
+        let mut hit = None;
+
+        for (n, (watch, last)) in self.watches.iter_mut().enumerate() {
+            let triggered = match *watch {
+                Watch::Memory { start, end, access } => chip8.accesses().iter().any(|a| {
+                    let kind_matches = match access {
+                        WatchAccess::Read => a.kind == AccessKind::Read,
+                        WatchAccess::Write => a.kind == AccessKind::Write,
+                        WatchAccess::Any => true
+                    };
+                    kind_matches && a.start <= end as usize && a.start + a.len > start as usize
+                }),
+                Watch::Register { condition, .. } => {
+                    let value = watch_value(watch, chip8);
+                    let changed = value != *last;
+                    *last = value;
+                    let value = value.unwrap_or(0);
+                    changed
+                        && match condition {
+                            WatchCondition::Changed => true,
+                            WatchCondition::Equal(target) => value == target,
+                            WatchCondition::NotEqual(target) => value != target,
+                            WatchCondition::Less(target) => value < target,
+                            WatchCondition::Greater(target) => value > target
+                        }
+                }
+            };
+
+            if triggered && hit.is_none() {
+                hit = Some(format!("Watchpoint {}: {}", n, describe_watch(watch)));
+            }
+        }
+
+        hit
+    }
+}
+
+// Current value of a register watchpoint's target
+fn watch_value(watch: &Watch, chip8: &Chip8) -> Option<u16> {
+    match *watch {
+        Watch::Memory { .. } => None,
+        Watch::Register { target, .. } => Some(match target {
+            WatchTarget::V(r) => chip8.v()[r as usize & 0xF] as u16,
+            WatchTarget::I => chip8.i(),
+            WatchTarget::DelayTimer => chip8.delay_timer() as u16,
+            WatchTarget::SoundTimer => chip8.sound_timer() as u16,
+            WatchTarget::StackDepth => chip8.stack().len() as u16
+        })
+    }
+}
+
+fn describe_watch(watch: &Watch) -> String {
+    match *watch {
+        Watch::Memory { start, end, access } => {
+            let access = match access {
+                WatchAccess::Read => "read",
+                WatchAccess::Write => "write",
+                WatchAccess::Any => "read / write"
+            };
+            if start == end {
+                format!("{} {:#05X}", access, start)
+            } else {
+                format!("{} {:#05X} - {:#05X}", access, start, end)
+            }
+        }
+        Watch::Register { target, condition } => {
+            let target = match target {
+                WatchTarget::V(r) => format!("V{:X}", r),
+                WatchTarget::I => "I".to_string(),
+                WatchTarget::DelayTimer => "DT".to_string(),
+                WatchTarget::SoundTimer => "ST".to_string(),
+                WatchTarget::StackDepth => "stack depth".to_string()
+            };
+            match condition {
+                WatchCondition::Changed => format!("{} changes", target),
+                WatchCondition::Equal(v) => format!("{} becomes == {:#X}", target, v),
+                WatchCondition::NotEqual(v) => format!("{} becomes != {:#X}", target, v),
+                WatchCondition::Less(v) => format!("{} becomes < {:#X}", target, v),
+                WatchCondition::Greater(v) => format!("{} becomes > {:#X}", target, v)
+            }
+        }
+    }
+}
+
+impl Default for Debugger {
+    fn default() -> Debugger {
+        Debugger::new()
+    }
+}
+
+fn current_instruction(chip8: &Chip8) -> Option<Instruction> {
+    let pc = chip8.pc() as usize;
+    let bytes = chip8.memory().get(pc..pc + 2)?;
+    Some(decode(((bytes[0] as u16) << 8) | bytes[1] as u16))
+}
+
+pub fn format_registers(chip8: &Chip8) -> String {
+    let v: Vec<String> = chip8.v().iter().enumerate().map(|(r, val)| format!("V{:X}={:02X}", r, val)).collect();
+    let stack: Vec<String> = chip8.stack().iter().map(|a| format!("{:03X}", a)).collect();
+    format!(
+        "PC={:03X} I={:03X} DT={:02X} ST={:02X}\n{}\n{}\nstack: [{}]",
+        chip8.pc(),
+        chip8.i(),
+        chip8.delay_timer(),
+        chip8.sound_timer(),
+        v[..8].join(" "),
+        v[8..].join(" "),
+        stack.join(" ")
+    )
+}
+
+// Instructions starting at pc, the current one marked
+pub fn format_listing(chip8: &Chip8, count: usize) -> String {
+    let memory = chip8.memory();
+    let mut lines = Vec::new();
+    let mut addr = chip8.pc() as usize;
+
+    for n in 0..count {
+        let bytes = match memory.get(addr..addr + 2) {
+            Some(bytes) => bytes,
+            None => break
+        };
+        let opcode = ((bytes[0] as u16) << 8) | bytes[1] as u16;
+        let marker = if n == 0 { "=>" } else { "  " };
+        lines.push(format!("{} {:#05X}  {:04X}  {}", marker, addr, opcode, decode(opcode)));
+        addr += 2;
+    }
+
+    lines.join("\n")
+}
@@ -0,0 +1,204 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+use crate::instruction::{decode, Instruction};
+
+// Programs are loaded at 0x200
+const ORIGIN: usize = 0x200;
+
+// Most data bytes on one line, so a row still fits the bytes column
+const DATA_PER_LINE: usize = 4;
+
+// One line of a disassembly: an instruction, or a run of data bytes
+pub struct Line {
+    pub address: u16,
+    pub bytes: Vec<u8>,
+    pub label: Option<String>,
+    pub kind: LineKind
+}
+
+pub enum LineKind {
+    Code(Instruction, String), // decoded instruction and its mnemonic, with labels substituted
+    Data
+}
+
+// Disassemble a ROM by following control flow from 0x200. Bytes never reached
+// as code are data, and targets of ANNN / F000 NNNN loads are labelled as data.
+pub fn disassemble(rom: &[u8]) -> Vec<Line> {
+    let code = trace_code(rom);
+    let labels = find_labels(rom, &code);
+
+    let mut lines = Vec::new();
+    let mut offset = 0;
+    while offset < rom.len() {
+        let address = (ORIGIN + offset) as u16;
+        let label = labels.get(&address).cloned();
+
+        if let Some(&len) = code.get(&address) {
+            let bytes = rom[offset..offset + len].to_vec();
+            let instruction = decode(word(rom, offset));
+            let mnemonic = mnemonic(&instruction, &bytes, &labels);
+            lines.push(Line { address, bytes, label, kind: LineKind::Code(instruction, mnemonic) });
+            offset += len;
+        } else {
+            // Data runs until code, a label or a full line
+            let mut end = offset + 1;
+            while end < rom.len() && end - offset < DATA_PER_LINE {
+                let next = (ORIGIN + end) as u16;
+                if code.contains_key(&next) || labels.contains_key(&next) {
+                    break;
+                }
+                end += 1;
+            }
+            lines.push(Line { address, bytes: rom[offset..end].to_vec(), label, kind: LineKind::Data });
+            offset = end;
+        }
+    }
+
+    lines
+}
+
+// Address and length of every instruction reachable from the entry point
+fn trace_code(rom: &[u8]) -> BTreeMap<u16, usize> {
+    let mut code = BTreeMap::new();
+    let mut pending = vec![ORIGIN];
+
+    while let Some(address) = pending.pop() {
+        let offset = match address.checked_sub(ORIGIN) {
+            Some(offset) if offset + 1 < rom.len() => offset,
+            _ => continue
+        };
+        if code.contains_key(&(address as u16)) {
+            continue;
+        }
+
+        let instruction = decode(word(rom, offset));
+        let len = if instruction == Instruction::SetILong { 4 } else { 2 };
+        if offset + len > rom.len() {
+            continue;
+        }
+        if let Instruction::Unknown(_) = instruction {
+            continue;
+        }
+        code.insert(address as u16, len);
+
+        let next = address + len;
+        match instruction {
+            // No fall through
+            Instruction::Return | Instruction::Exit => {}
+            Instruction::Jump { nnn } => {
+                // Jump to self is the usual way to halt
+                if nnn as usize != address {
+                    pending.push(nnn as usize);
+                }
+            }
+            // Target depends on a register, only the base is known
+            Instruction::JumpOffset { nnn, .. } => pending.push(nnn as usize),
+            Instruction::Call { nnn } => {
+                pending.push(nnn as usize);
+                pending.push(next);
+            }
+            Instruction::SkipEqImm { .. }
+            | Instruction::SkipNeImm { .. }
+            | Instruction::SkipEqReg { .. }
+            | Instruction::SkipNeReg { .. }
+            | Instruction::SkipKey { .. }
+            | Instruction::SkipNotKey { .. } => {
+                pending.push(next);
+                let skipped = if next >= ORIGIN && rom.get(next - ORIGIN..next - ORIGIN + 2) == Some(&[0xF0, 0x00]) {
+                    4
+                } else {
+                    2
+                };
+                pending.push(next + skipped);
+            }
+            _ => pending.push(next)
+        }
+    }
+
+    code
+}
+
+// Labels for call, jump and data targets inside the ROM
+fn find_labels(rom: &[u8], code: &BTreeMap<u16, usize>) -> BTreeMap<u16, String> {
+    let mut calls = BTreeSet::new();
+    let mut jumps = BTreeSet::new();
+    let mut data = BTreeSet::new();
+
+    for (&address, &len) in code {
+        let offset = address as usize - ORIGIN;
+        match decode(word(rom, offset)) {
+            Instruction::Call { nnn } => {
+                calls.insert(nnn);
+            }
+            Instruction::Jump { nnn } | Instruction::JumpOffset { nnn, .. } => {
+                jumps.insert(nnn);
+            }
+            Instruction::SetI { nnn } => {
+                data.insert(nnn);
+            }
+            Instruction::SetILong if len == 4 => {
+                data.insert(word(rom, offset + 2));
+            }
+            _ => {}
+        }
+    }
+
+    let in_rom = |address: &u16| (ORIGIN..ORIGIN + rom.len()).contains(&(*address as usize));
+    let mut labels = BTreeMap::new();
+    for address in data.iter().filter(|a| in_rom(a) && !code.contains_key(a)) {
+        labels.insert(*address, format!("data_{:03X}", address));
+    }
+    for address in jumps.iter().filter(|a| in_rom(a)) {
+        labels.insert(*address, format!("L{:03X}", address));
+    }
+    for address in calls.iter().filter(|a| in_rom(a)) {
+        labels.insert(*address, format!("sub_{:03X}", address));
+    }
+
+    labels
+}
+
+// Mnemonic with jump, call and load targets replaced by their labels
+fn mnemonic(instruction: &Instruction, bytes: &[u8], labels: &BTreeMap<u16, String>) -> String {
+    let label = |address: u16| labels.get(&address).cloned().unwrap_or(format!("{:#05X}", address));
+
+    match *instruction {
+        Instruction::Jump { nnn } => format!("JP {}", label(nnn)),
+        Instruction::Call { nnn } => format!("CALL {}", label(nnn)),
+        Instruction::SetI { nnn } => format!("LD I, {}", label(nnn)),
+        Instruction::JumpOffset { nnn, .. } => format!("JP V0, {}", label(nnn)),
+        Instruction::SetILong => {
+            let address = ((bytes[2] as u16) << 8) | bytes[3] as u16;
+            let target = labels.get(&address).cloned().unwrap_or(format!("{:#06X}", address));
+            format!("LD I, LONG {}", target)
+        }
+        _ => instruction.to_string()
+    }
+}
+
+fn word(rom: &[u8], offset: usize) -> u16 {
+    ((rom[offset] as u16) << 8) | rom[offset + 1] as u16
+}
+
+impl fmt::Display for Line {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(label) = &self.label {
+            writeln!(f, "{}:", label)?;
+        }
+
+        let bytes: Vec<String> = self.bytes.iter().map(|b| format!("{:02X}", b)).collect();
+        write!(f, "  {:#05X}  {:<12}", self.address, bytes.join(" "))?;
+
+        match &self.kind {
+            LineKind::Code(_, mnemonic) => write!(f, "{}", mnemonic),
+            LineKind::Data => {
+                // Show data bytes as sprite rows too
+                let values: Vec<String> = self.bytes.iter().map(|b| format!("{:#04X}", b)).collect();
+                let pixels: Vec<String> =
+                    self.bytes.iter().map(|&b| (0..8).rev().map(|i| if (b >> i) & 1 == 1 { '#' } else { '.' }).collect()).collect();
+                write!(f, ":byte {}  ; {}", values.join(" "), pixels.join(" "))
+            }
+        }
+    }
+}
@@ -0,0 +1,64 @@
+use std::fmt;
+
+// Errors raised while loading or running a program. Execution errors carry
+// the address and opcode of the offending instruction.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Chip8Error {
+    StackUnderflow { pc: u16, opcode: u16 }, // 00EE with an empty stack
+    StackOverflow { pc: u16, opcode: u16 }, // 2NNN with a full stack
+    MemoryOutOfBounds { pc: u16, opcode: u16, address: usize }, // access past the end of memory
+    PcOutOfBounds { pc: u16 }, // fetch past the end of memory
+    RomTooLarge { size: usize, max: usize }, // ROM does not fit in memory after 0x200
+    UnknownOpcode { pc: u16, opcode: u16 }
+}
+
+impl fmt::Display for Chip8Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Chip8Error::StackUnderflow { pc, opcode } => {
+                write!(f, "stack underflow at {:#05X} ({:#06X}): return with an empty stack", pc, opcode)
+            }
+            Chip8Error::StackOverflow { pc, opcode } => {
+                write!(f, "stack overflow at {:#05X} ({:#06X}): too many nested calls", pc, opcode)
+            }
+            Chip8Error::MemoryOutOfBounds { pc, opcode, address } => {
+                write!(f, "memory access out of bounds at {:#05X} ({:#06X}): address {:#X}", pc, opcode, address)
+            }
+            Chip8Error::PcOutOfBounds { pc } => {
+                write!(f, "program counter out of bounds: {:#X}", pc)
+            }
+            Chip8Error::RomTooLarge { size, max } => {
+                write!(f, "ROM too large: {} bytes, at most {} fit in memory", size, max)
+            }
+            Chip8Error::UnknownOpcode { pc, opcode } => {
+                write!(f, "unknown opcode at {:#05X}: {:#06X}", pc, opcode)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Chip8Error {}
+
+// Errors restoring a save state
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum StateError {
+    BadMagic, // not a save state
+    UnsupportedVersion(u16),
+    Truncated,
+    ChecksumMismatch,
+    Invalid(&'static str) // well formed, but describes an impossible machine
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StateError::BadMagic => write!(f, "not a save state"),
+            StateError::UnsupportedVersion(version) => write!(f, "unsupported save state version {}", version),
+            StateError::Truncated => write!(f, "save state is truncated"),
+            StateError::ChecksumMismatch => write!(f, "save state checksum does not match, the file is corrupt"),
+            StateError::Invalid(what) => write!(f, "invalid save state: {}", what)
+        }
+    }
+}
+
+impl std::error::Error for StateError {}
@@ -0,0 +1,243 @@
+// Game controller buttons onto the hex keypad. Nothing here talks to SDL:
+// the frontend turns its controller events into PadInputs and gets back the
+// keypad keys to press or release.
+
+use crate::config::{hex_key_setting, Config, Entry};
+use crate::romdb::RomInfo;
+
+// Controller buttons that can be bound, named as in SDL's controller mappings
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Button {
+    DPadUp,
+    DPadDown,
+    DPadLeft,
+    DPadRight,
+    A,
+    B,
+    X,
+    Y,
+    LeftShoulder,
+    RightShoulder,
+    LeftTrigger,
+    RightTrigger,
+    Back,
+    Start
+}
+
+impl Button {
+    pub const ALL: [Button; 14] = [
+        Button::DPadUp, Button::DPadDown, Button::DPadLeft, Button::DPadRight,
+        Button::A, Button::B, Button::X, Button::Y,
+        Button::LeftShoulder, Button::RightShoulder, Button::LeftTrigger, Button::RightTrigger,
+        Button::Back, Button::Start
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Button::DPadUp => "dpup",
+            Button::DPadDown => "dpdown",
+            Button::DPadLeft => "dpleft",
+            Button::DPadRight => "dpright",
+            Button::A => "a",
+            Button::B => "b",
+            Button::X => "x",
+            Button::Y => "y",
+            Button::LeftShoulder => "leftshoulder",
+            Button::RightShoulder => "rightshoulder",
+            Button::LeftTrigger => "lefttrigger",
+            Button::RightTrigger => "righttrigger",
+            Button::Back => "back",
+            Button::Start => "start"
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Button> {
+        Button::ALL.into_iter().find(|b| b.name().eq_ignore_ascii_case(name))
+    }
+}
+
+// Analog inputs. The left stick works as the D-pad, the triggers as buttons.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Axis {
+    LeftX,
+    LeftY,
+    TriggerLeft,
+    TriggerRight
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PadInput {
+    Button(Button, bool), // pressed
+    Axis(Axis, i16) // -32768 - 32767, up and left are negative
+}
+
+// How far a stick or trigger has to go to count as pressed
+pub const AXIS_THRESHOLD: i16 = 16_000;
+
+// Which buttons press which hex keys. A hex key can have any number of
+// buttons, a button presses at most one hex key.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PadMap {
+    bindings: Vec<(Button, u8)>
+}
+
+impl PadMap {
+    // The D-pad as W A S D and the buttons around it as the keys near those
+    pub fn new() -> PadMap {
+        let mut map = PadMap { bindings: Vec::new() };
+        map.add(Button::DPadUp, 0x5);
+        map.add(Button::DPadLeft, 0x7);
+        map.add(Button::DPadDown, 0x8);
+        map.add(Button::DPadRight, 0x9);
+        map.add(Button::A, 0x6);
+        map.add(Button::B, 0x4);
+        map.add(Button::X, 0x1);
+        map.add(Button::Y, 0x2);
+        map.add(Button::LeftShoulder, 0x3);
+        map.add(Button::RightShoulder, 0xC);
+        map
+    }
+
+    // The controller profile for a ROM: the defaults, then the config's
+    // [global] pad.<hex> entries, the ROM database's actions and the ROM's own
+    // section, so the later ones win. Errors name the config line.
+    pub fn from_config(config: &Config, rom: &[u8], info: Option<&RomInfo>) -> Result<PadMap, String> {
+        let mut map = PadMap::new();
+        map.bind_entries(config.global_entries())?;
+        if let Some(info) = info {
+            map.bind_actions(&info.keys);
+        }
+        map.bind_entries(config.rom_entries(rom))?;
+        Ok(map)
+    }
+
+    fn bind_entries<'a>(&mut self, entries: impl IntoIterator<Item = &'a Entry>) -> Result<(), String> {
+        for entry in entries {
+            if let Some(hex) = hex_key_setting(&entry.key, "pad.") {
+                hex.and_then(|hex| self.bind(hex, &entry.value)).map_err(|e| format!("line {}: {}", entry.line, e))?;
+            }
+        }
+        Ok(())
+    }
+
+    // The hex key a button presses
+    pub fn hex_key(&self, button: Button) -> Option<u8> {
+        self.bindings.iter().find(|&&(b, _)| b == button).map(|&(_, hex)| hex)
+    }
+
+    pub fn buttons_for(&self, hex: u8) -> impl Iterator<Item = Button> + '_ {
+        self.bindings.iter().filter(move |&&(_, h)| h == hex).map(|&(button, _)| button)
+    }
+
+    // Bind a button to a hex key, taking it off the one it pressed before
+    pub fn add(&mut self, button: Button, hex: u8) {
+        self.bindings.retain(|&(b, _)| b != button);
+        self.bindings.push((button, hex & 0xF));
+    }
+
+    // Replace a hex key's buttons with a comma separated list, like "dpleft, x"
+    pub fn bind(&mut self, hex: u8, names: &str) -> Result<(), String> {
+        let buttons = names
+            .split(',')
+            .map(str::trim)
+            .filter(|n| !n.is_empty())
+            .map(|name| Button::from_name(name).ok_or(format!("unknown controller button '{name}'")))
+            .collect::<Result<Vec<_>, _>>()?;
+        self.bindings.retain(|&(_, h)| h != hex);
+        for button in buttons {
+            self.add(button, hex);
+        }
+        Ok(())
+    }
+
+    // Bind the actions of a ROM database entry, like ("left", 4): directions
+    // go on the D-pad, a and b on those buttons. Other actions are left alone.
+    pub fn bind_actions(&mut self, actions: &[(String, u8)]) {
+        for (action, hex) in actions {
+            let button = match action.as_str() {
+                "up" => Button::DPadUp,
+                "down" => Button::DPadDown,
+                "left" => Button::DPadLeft,
+                "right" => Button::DPadRight,
+                "a" => Button::A,
+                "b" => Button::B,
+                _ => continue
+            };
+            self.add(button, *hex);
+        }
+    }
+}
+
+impl Default for PadMap {
+    fn default() -> PadMap {
+        PadMap::new()
+    }
+}
+
+// What is held on the connected controllers, turned into keypad presses. A
+// hex key stays down while any button bound to it is held, on any controller.
+#[derive(Clone, Debug, Default)]
+pub struct Gamepads {
+    map: PadMap,
+    held: Vec<(u32, Button)>, // controller id and button
+    stick: Vec<(u32, Button)> // D-pad directions the left stick is pushed in
+}
+
+impl Gamepads {
+    pub fn new(map: PadMap) -> Gamepads {
+        Gamepads { map, held: Vec::new(), stick: Vec::new() }
+    }
+
+    pub fn map(&self) -> &PadMap {
+        &self.map
+    }
+
+    // Hex keys held down, bit n for key n
+    pub fn keys(&self) -> u16 {
+        self.held
+            .iter()
+            .chain(&self.stick)
+            .filter_map(|&(_, button)| self.map.hex_key(button))
+            .fold(0, |keys, hex| keys | 1 << hex)
+    }
+
+    // Apply an input from a controller, returning the hex keys that went down
+    // (true) or up (false)
+    pub fn input(&mut self, pad: u32, input: PadInput) -> Vec<(u8, bool)> {
+        let before = self.keys();
+        match input {
+            PadInput::Button(button, pressed) => set_held(&mut self.held, pad, button, pressed),
+            PadInput::Axis(Axis::LeftX, value) => {
+                set_held(&mut self.stick, pad, Button::DPadLeft, value <= -AXIS_THRESHOLD);
+                set_held(&mut self.stick, pad, Button::DPadRight, value >= AXIS_THRESHOLD);
+            }
+            PadInput::Axis(Axis::LeftY, value) => {
+                set_held(&mut self.stick, pad, Button::DPadUp, value <= -AXIS_THRESHOLD);
+                set_held(&mut self.stick, pad, Button::DPadDown, value >= AXIS_THRESHOLD);
+            }
+            PadInput::Axis(Axis::TriggerLeft, value) => set_held(&mut self.held, pad, Button::LeftTrigger, value >= AXIS_THRESHOLD),
+            PadInput::Axis(Axis::TriggerRight, value) => set_held(&mut self.held, pad, Button::RightTrigger, value >= AXIS_THRESHOLD)
+        }
+        changes(before, self.keys())
+    }
+
+    // Let go of everything a controller held, as when it is unplugged
+    pub fn disconnect(&mut self, pad: u32) -> Vec<(u8, bool)> {
+        let before = self.keys();
+        self.held.retain(|&(p, _)| p != pad);
+        self.stick.retain(|&(p, _)| p != pad);
+        changes(before, self.keys())
+    }
+}
+
+fn set_held(held: &mut Vec<(u32, Button)>, pad: u32, button: Button, pressed: bool) {
+    held.retain(|&entry| entry != (pad, button));
+    if pressed {
+        held.push((pad, button));
+    }
+}
+
+// Keys that differ between two key masks, lowest first
+fn changes(before: u16, after: u16) -> Vec<(u8, bool)> {
+    (0..16).filter(|hex| (before ^ after) & 1 << hex != 0).map(|hex| (hex, after & 1 << hex != 0)).collect()
+}
@@ -0,0 +1,217 @@
+use crate::cpu::Chip8;
+use crate::crc32::crc32;
+use crate::error::Chip8Error;
+
+// Running a ROM without a window, for batch jobs and CI: a fixed number of
+// frames or cycles, scripted key input, and dumps of the final machine state.
+
+// Gray levels for the display dumps, indexed by pixel value like the SDL palette
+const GRAY: [u8; 4] = [0, 255, 170, 85];
+
+// How long to run for
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Limit {
+    Frames(u64),
+    Cycles(u64)
+}
+
+// A key change at the start of a frame
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct KeyEvent {
+    pub frame: u64,
+    pub key: u8,
+    pub pressed: bool
+}
+
+// How a run ended
+#[derive(Debug)]
+pub struct RunResult {
+    pub frames: u64, // completed frames
+    pub cycles: u64, // executed instructions
+    pub exited: bool, // the program ran 00FD
+    pub error: Option<Chip8Error>
+}
+
+// Parse a key script: one event per line, `<frame> down|up <key>`, with the
+// key as a hex digit. Blank lines and lines starting with # are ignored.
+pub fn parse_key_script(script: &str) -> Result<Vec<KeyEvent>, String> {
+    let mut events = Vec::new();
+
+    for (n, line) in script.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let event = match words[..] {
+            [frame, action, key] => {
+                let frame = frame.parse().map_err(|_| format!("line {}: invalid frame '{}'", n + 1, frame))?;
+                let pressed = match action {
+                    "down" => true,
+                    "up" => false,
+                    _ => return Err(format!("line {}: expected 'down' or 'up', found '{}'", n + 1, action))
+                };
+                let key = match u8::from_str_radix(key, 16) {
+                    Ok(key) if key < 16 => key,
+                    _ => return Err(format!("line {}: invalid key '{}'", n + 1, key))
+                };
+                KeyEvent { frame, key, pressed }
+            }
+            _ => return Err(format!("line {}: expected '<frame> down|up <key>'", n + 1))
+        };
+        events.push(event);
+    }
+
+    // Events on the same frame keep their order
+    events.sort_by_key(|e| e.frame);
+    Ok(events)
+}
+
+// Run until the limit, an exit or an error. A frame is cycles_per_frame
+// instructions followed by a 60 Hz timer tick. Keys must be sorted by frame.
+pub fn run(chip8: &mut Chip8, limit: Limit, cycles_per_frame: u64, keys: &[KeyEvent]) -> RunResult {
+    let mut result = RunResult { frames: 0, cycles: 0, exited: false, error: None };
+    let mut keys = keys.iter().peekable();
+
+    loop {
+        if let Limit::Frames(frames) = limit {
+            if result.frames >= frames {
+                return result;
+            }
+        }
+
+        while let Some(event) = keys.next_if(|e| e.frame <= result.frames) {
+            chip8.set_key(event.key, event.pressed);
+        }
+
+        // A cycle limit can end the run partway through a frame
+        let mut left = match limit {
+            Limit::Cycles(cycles) => cycles - result.cycles,
+            Limit::Frames(_) => u64::MAX
+        };
+        let frame = chip8.run_frame_until(cycles_per_frame, |_| {
+            if left == 0 {
+                return true;
+            }
+            left -= 1;
+            false
+        });
+
+        result.cycles += frame.cycles;
+        if !frame.completed() {
+            result.exited = frame.exited;
+            result.error = frame.error;
+            return result;
+        }
+        result.frames += 1;
+    }
+}
+
+// The display as text, one line per row: . for off, # for plane 1,
+// + for plane 2 and @ for both
+pub fn display_ascii(chip8: &Chip8) -> String {
+    let (width, _) = chip8.resolution();
+    let mut text = String::new();
+    for row in chip8.display().chunks(width) {
+        text.extend(row.iter().map(|&px| ['.', '#', '+', '@'][px as usize & 3]));
+        text.push('\n');
+    }
+    text
+}
+
+// The display as a binary PBM (P4), lit pixels black
+pub fn display_pbm(chip8: &Chip8) -> Vec<u8> {
+    let (width, height) = chip8.resolution();
+    let mut pbm = format!("P4\n{} {}\n", width, height).into_bytes();
+    for row in chip8.display().chunks(width) {
+        for bits in row.chunks(8) {
+            let byte = bits.iter().enumerate().fold(0, |byte, (n, &px)| byte | ((px != 0) as u8) << (7 - n));
+            pbm.push(byte);
+        }
+    }
+    pbm
+}
+
+// The display as an 8-bit grayscale PNG, with the emulator's colours
+pub fn display_png(chip8: &Chip8) -> Vec<u8> {
+    let (width, height) = chip8.resolution();
+
+    // Each scanline starts with filter type 0 (none)
+    let mut raw = Vec::with_capacity((width + 1) * height);
+    for row in chip8.display().chunks(width) {
+        raw.push(0);
+        raw.extend(row.iter().map(|&px| GRAY[px as usize & 3]));
+    }
+
+    let mut header = Vec::new();
+    header.extend_from_slice(&(width as u32).to_be_bytes());
+    header.extend_from_slice(&(height as u32).to_be_bytes());
+    header.extend_from_slice(&[8, 0, 0, 0, 0]); // bit depth, grayscale, deflate, no filter, no interlace
+
+    let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+    png_chunk(&mut png, b"IHDR", &header);
+    png_chunk(&mut png, b"IDAT", &zlib_stored(&raw));
+    png_chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+fn png_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    let crc = crc32(&png[start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+// A zlib stream of uncompressed deflate blocks. The display is at most 8 KiB,
+// so compressing it is not worth the code.
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x78, 0x01];
+    let mut blocks = data.chunks(0xFFFF).peekable();
+    if blocks.peek().is_none() {
+        out.extend_from_slice(&[1, 0, 0, 0xFF, 0xFF]);
+    }
+    while let Some(block) = blocks.next() {
+        out.push(blocks.peek().is_none() as u8); // BFINAL on the last block
+        out.extend_from_slice(&(block.len() as u16).to_le_bytes());
+        out.extend_from_slice(&(!(block.len() as u16)).to_le_bytes());
+        out.extend_from_slice(block);
+    }
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in data {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+// Registers and run statistics as a JSON object
+pub fn registers_json(chip8: &Chip8, result: &RunResult) -> String {
+    let list = |values: Vec<String>| values.join(", ");
+    let error = match &result.error {
+        Some(e) => format!("\"{}\"", e.to_string().replace('\\', "\\\\").replace('"', "\\\"")),
+        None => "null".to_string()
+    };
+
+    format!(
+        "{{\n  \"pc\": {},\n  \"i\": {},\n  \"v\": [{}],\n  \"stack\": [{}],\n  \"delay_timer\": {},\n  \
+         \"sound_timer\": {},\n  \"frames\": {},\n  \"cycles\": {},\n  \"exited\": {},\n  \"error\": {}\n}}",
+        chip8.pc(),
+        chip8.i(),
+        list(chip8.v().iter().map(|v| v.to_string()).collect()),
+        list(chip8.stack().iter().map(|a| a.to_string()).collect()),
+        chip8.delay_timer(),
+        chip8.sound_timer(),
+        result.frames,
+        result.cycles,
+        result.exited,
+        error
+    )
+}
@@ -0,0 +1,249 @@
+use std::fmt;
+
+// A decoded CHIP-8 / SUPER-CHIP / XO-CHIP instruction.
+// x and y are register indices, n / nn / nnn the 4, 8 and 12 bit immediates.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Instruction {
+    Clear, // 00E0
+    Return, // 00EE
+    ScrollDown { n: u8 }, // 00CN
+    ScrollRight, // 00FB
+    ScrollLeft, // 00FC
+    Exit, // 00FD
+    LowRes, // 00FE
+    HighRes, // 00FF
+    Jump { nnn: u16 }, // 1NNN
+    Call { nnn: u16 }, // 2NNN
+    SkipEqImm { x: u8, nn: u8 }, // 3XNN
+    SkipNeImm { x: u8, nn: u8 }, // 4XNN
+    SkipEqReg { x: u8, y: u8 }, // 5XY0
+    StoreRange { x: u8, y: u8 }, // 5XY2
+    LoadRange { x: u8, y: u8 }, // 5XY3
+    SetImm { x: u8, nn: u8 }, // 6XNN
+    AddImm { x: u8, nn: u8 }, // 7XNN
+    SetReg { x: u8, y: u8 }, // 8XY0
+    Or { x: u8, y: u8 }, // 8XY1
+    And { x: u8, y: u8 }, // 8XY2
+    Xor { x: u8, y: u8 }, // 8XY3
+    AddReg { x: u8, y: u8 }, // 8XY4
+    SubXY { x: u8, y: u8 }, // 8XY5
+    ShiftRight { x: u8, y: u8 }, // 8XY6
+    SubYX { x: u8, y: u8 }, // 8XY7
+    ShiftLeft { x: u8, y: u8 }, // 8XYE
+    SkipNeReg { x: u8, y: u8 }, // 9XY0
+    SetI { nnn: u16 }, // ANNN
+    JumpOffset { x: u8, nnn: u16 }, // BNNN / BXNN, x is the top nibble of nnn
+    Random { x: u8, nn: u8 }, // CXNN
+    Draw { x: u8, y: u8, n: u8 }, // DXYN
+    SkipKey { x: u8 }, // EX9E
+    SkipNotKey { x: u8 }, // EXA1
+    SetILong, // F000 NNNN, the address is the following word
+    SelectPlane { n: u8 }, // FN01
+    AudioPattern, // F002
+    GetDelay { x: u8 }, // FX07
+    WaitKey { x: u8 }, // FX0A
+    SetDelay { x: u8 }, // FX15
+    SetSound { x: u8 }, // FX18
+    AddI { x: u8 }, // FX1E
+    Font { x: u8 }, // FX29
+    BigFont { x: u8 }, // FX30
+    Bcd { x: u8 }, // FX33
+    SetPitch { x: u8 }, // FX3A
+    StoreRegs { x: u8 }, // FX55
+    LoadRegs { x: u8 }, // FX65
+    SaveFlags { x: u8 }, // FX75
+    LoadFlags { x: u8 }, // FX85
+    Unknown(u16)
+}
+
+pub fn decode(opcode: u16) -> Instruction {
+    // Extract 16 bits into 4 nibbles, and the 8 / 12 bit immediates
+    let n1 = (opcode >> 12) as u8;
+    let x = ((opcode >> 8) & 0xF) as u8;
+    let y = ((opcode >> 4) & 0xF) as u8;
+    let n = (opcode & 0xF) as u8;
+    let nn = (opcode & 0xFF) as u8;
+    let nnn = opcode & 0xFFF;
+
+    match opcode {
+        0x00E0 => return Instruction::Clear,
+        0x00EE => return Instruction::Return,
+        0x00C0..=0x00CF => return Instruction::ScrollDown { n },
+        0x00FB => return Instruction::ScrollRight,
+        0x00FC => return Instruction::ScrollLeft,
+        0x00FD => return Instruction::Exit,
+        0x00FE => return Instruction::LowRes,
+        0x00FF => return Instruction::HighRes,
+        0xF000 => return Instruction::SetILong,
+        0xF002 => return Instruction::AudioPattern,
+        _ => {}
+    }
+
+    match (n1, n) {
+        (0x1, _) => Instruction::Jump { nnn },
+        (0x2, _) => Instruction::Call { nnn },
+        (0x3, _) => Instruction::SkipEqImm { x, nn },
+        (0x4, _) => Instruction::SkipNeImm { x, nn },
+        (0x5, 0x0) => Instruction::SkipEqReg { x, y },
+        (0x5, 0x2) => Instruction::StoreRange { x, y },
+        (0x5, 0x3) => Instruction::LoadRange { x, y },
+        (0x6, _) => Instruction::SetImm { x, nn },
+        (0x7, _) => Instruction::AddImm { x, nn },
+        (0x8, 0x0) => Instruction::SetReg { x, y },
+        (0x8, 0x1) => Instruction::Or { x, y },
+        (0x8, 0x2) => Instruction::And { x, y },
+        (0x8, 0x3) => Instruction::Xor { x, y },
+        (0x8, 0x4) => Instruction::AddReg { x, y },
+        (0x8, 0x5) => Instruction::SubXY { x, y },
+        (0x8, 0x6) => Instruction::ShiftRight { x, y },
+        (0x8, 0x7) => Instruction::SubYX { x, y },
+        (0x8, 0xE) => Instruction::ShiftLeft { x, y },
+        (0x9, 0x0) => Instruction::SkipNeReg { x, y },
+        (0xA, _) => Instruction::SetI { nnn },
+        (0xB, _) => Instruction::JumpOffset { x, nnn },
+        (0xC, _) => Instruction::Random { x, nn },
+        (0xD, _) => Instruction::Draw { x, y, n },
+        (0xE, _) => match nn {
+            0x9E => Instruction::SkipKey { x },
+            0xA1 => Instruction::SkipNotKey { x },
+            _ => Instruction::Unknown(opcode)
+        },
+        (0xF, _) => match nn {
+            0x01 => Instruction::SelectPlane { n: x },
+            0x07 => Instruction::GetDelay { x },
+            0x0A => Instruction::WaitKey { x },
+            0x15 => Instruction::SetDelay { x },
+            0x18 => Instruction::SetSound { x },
+            0x1E => Instruction::AddI { x },
+            0x29 => Instruction::Font { x },
+            0x30 => Instruction::BigFont { x },
+            0x33 => Instruction::Bcd { x },
+            0x3A => Instruction::SetPitch { x },
+            0x55 => Instruction::StoreRegs { x },
+            0x65 => Instruction::LoadRegs { x },
+            0x75 => Instruction::SaveFlags { x },
+            0x85 => Instruction::LoadFlags { x },
+            _ => Instruction::Unknown(opcode)
+        },
+        _ => Instruction::Unknown(opcode)
+    }
+}
+
+impl Instruction {
+    // Opcode for this instruction, the inverse of decode
+    pub fn encode(&self) -> u16 {
+        let xy = |op: u16, x: u8, y: u8, n: u16| op | (x as u16) << 8 | (y as u16) << 4 | n;
+        let xnn = |op: u16, x: u8, nn: u8| op | (x as u16) << 8 | nn as u16;
+
+        match *self {
+            Instruction::Clear => 0x00E0,
+            Instruction::Return => 0x00EE,
+            Instruction::ScrollDown { n } => 0x00C0 | n as u16,
+            Instruction::ScrollRight => 0x00FB,
+            Instruction::ScrollLeft => 0x00FC,
+            Instruction::Exit => 0x00FD,
+            Instruction::LowRes => 0x00FE,
+            Instruction::HighRes => 0x00FF,
+            Instruction::Jump { nnn } => 0x1000 | nnn,
+            Instruction::Call { nnn } => 0x2000 | nnn,
+            Instruction::SkipEqImm { x, nn } => xnn(0x3000, x, nn),
+            Instruction::SkipNeImm { x, nn } => xnn(0x4000, x, nn),
+            Instruction::SkipEqReg { x, y } => xy(0x5000, x, y, 0x0),
+            Instruction::StoreRange { x, y } => xy(0x5000, x, y, 0x2),
+            Instruction::LoadRange { x, y } => xy(0x5000, x, y, 0x3),
+            Instruction::SetImm { x, nn } => xnn(0x6000, x, nn),
+            Instruction::AddImm { x, nn } => xnn(0x7000, x, nn),
+            Instruction::SetReg { x, y } => xy(0x8000, x, y, 0x0),
+            Instruction::Or { x, y } => xy(0x8000, x, y, 0x1),
+            Instruction::And { x, y } => xy(0x8000, x, y, 0x2),
+            Instruction::Xor { x, y } => xy(0x8000, x, y, 0x3),
+            Instruction::AddReg { x, y } => xy(0x8000, x, y, 0x4),
+            Instruction::SubXY { x, y } => xy(0x8000, x, y, 0x5),
+            Instruction::ShiftRight { x, y } => xy(0x8000, x, y, 0x6),
+            Instruction::SubYX { x, y } => xy(0x8000, x, y, 0x7),
+            Instruction::ShiftLeft { x, y } => xy(0x8000, x, y, 0xE),
+            Instruction::SkipNeReg { x, y } => xy(0x9000, x, y, 0x0),
+            Instruction::SetI { nnn } => 0xA000 | nnn,
+            Instruction::JumpOffset { nnn, .. } => 0xB000 | nnn,
+            Instruction::Random { x, nn } => xnn(0xC000, x, nn),
+            Instruction::Draw { x, y, n } => xy(0xD000, x, y, n as u16),
+            Instruction::SkipKey { x } => xnn(0xE000, x, 0x9E),
+            Instruction::SkipNotKey { x } => xnn(0xE000, x, 0xA1),
+            Instruction::SetILong => 0xF000,
+            Instruction::SelectPlane { n } => xnn(0xF000, n, 0x01),
+            Instruction::AudioPattern => 0xF002,
+            Instruction::GetDelay { x } => xnn(0xF000, x, 0x07),
+            Instruction::WaitKey { x } => xnn(0xF000, x, 0x0A),
+            Instruction::SetDelay { x } => xnn(0xF000, x, 0x15),
+            Instruction::SetSound { x } => xnn(0xF000, x, 0x18),
+            Instruction::AddI { x } => xnn(0xF000, x, 0x1E),
+            Instruction::Font { x } => xnn(0xF000, x, 0x29),
+            Instruction::BigFont { x } => xnn(0xF000, x, 0x30),
+            Instruction::Bcd { x } => xnn(0xF000, x, 0x33),
+            Instruction::SetPitch { x } => xnn(0xF000, x, 0x3A),
+            Instruction::StoreRegs { x } => xnn(0xF000, x, 0x55),
+            Instruction::LoadRegs { x } => xnn(0xF000, x, 0x65),
+            Instruction::SaveFlags { x } => xnn(0xF000, x, 0x75),
+            Instruction::LoadFlags { x } => xnn(0xF000, x, 0x85),
+            Instruction::Unknown(opcode) => opcode
+        }
+    }
+}
+
+// Mnemonics in the common Cowgod style, with the SUPER-CHIP and XO-CHIP extensions
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Instruction::Clear => write!(f, "CLS"),
+            Instruction::Return => write!(f, "RET"),
+            Instruction::ScrollDown { n } => write!(f, "SCD {}", n),
+            Instruction::ScrollRight => write!(f, "SCR"),
+            Instruction::ScrollLeft => write!(f, "SCL"),
+            Instruction::Exit => write!(f, "EXIT"),
+            Instruction::LowRes => write!(f, "LOW"),
+            Instruction::HighRes => write!(f, "HIGH"),
+            Instruction::Jump { nnn } => write!(f, "JP {:#05X}", nnn),
+            Instruction::Call { nnn } => write!(f, "CALL {:#05X}", nnn),
+            Instruction::SkipEqImm { x, nn } => write!(f, "SE V{:X}, {:#04X}", x, nn),
+            Instruction::SkipNeImm { x, nn } => write!(f, "SNE V{:X}, {:#04X}", x, nn),
+            Instruction::SkipEqReg { x, y } => write!(f, "SE V{:X}, V{:X}", x, y),
+            Instruction::StoreRange { x, y } => write!(f, "SAVE V{:X} - V{:X}", x, y),
+            Instruction::LoadRange { x, y } => write!(f, "LOAD V{:X} - V{:X}", x, y),
+            Instruction::SetImm { x, nn } => write!(f, "LD V{:X}, {:#04X}", x, nn),
+            Instruction::AddImm { x, nn } => write!(f, "ADD V{:X}, {:#04X}", x, nn),
+            Instruction::SetReg { x, y } => write!(f, "LD V{:X}, V{:X}", x, y),
+            Instruction::Or { x, y } => write!(f, "OR V{:X}, V{:X}", x, y),
+            Instruction::And { x, y } => write!(f, "AND V{:X}, V{:X}", x, y),
+            Instruction::Xor { x, y } => write!(f, "XOR V{:X}, V{:X}", x, y),
+            Instruction::AddReg { x, y } => write!(f, "ADD V{:X}, V{:X}", x, y),
+            Instruction::SubXY { x, y } => write!(f, "SUB V{:X}, V{:X}", x, y),
+            Instruction::ShiftRight { x, y } => write!(f, "SHR V{:X}, V{:X}", x, y),
+            Instruction::SubYX { x, y } => write!(f, "SUBN V{:X}, V{:X}", x, y),
+            Instruction::ShiftLeft { x, y } => write!(f, "SHL V{:X}, V{:X}", x, y),
+            Instruction::SkipNeReg { x, y } => write!(f, "SNE V{:X}, V{:X}", x, y),
+            Instruction::SetI { nnn } => write!(f, "LD I, {:#05X}", nnn),
+            Instruction::JumpOffset { nnn, .. } => write!(f, "JP V0, {:#05X}", nnn),
+            Instruction::Random { x, nn } => write!(f, "RND V{:X}, {:#04X}", x, nn),
+            Instruction::Draw { x, y, n } => write!(f, "DRW V{:X}, V{:X}, {}", x, y, n),
+            Instruction::SkipKey { x } => write!(f, "SKP V{:X}", x),
+            Instruction::SkipNotKey { x } => write!(f, "SKNP V{:X}", x),
+            Instruction::SetILong => write!(f, "LD I, LONG"),
+            Instruction::SelectPlane { n } => write!(f, "PLANE {}", n),
+            Instruction::AudioPattern => write!(f, "AUDIO"),
+            Instruction::GetDelay { x } => write!(f, "LD V{:X}, DT", x),
+            Instruction::WaitKey { x } => write!(f, "LD V{:X}, K", x),
+            Instruction::SetDelay { x } => write!(f, "LD DT, V{:X}", x),
+            Instruction::SetSound { x } => write!(f, "LD ST, V{:X}", x),
+            Instruction::AddI { x } => write!(f, "ADD I, V{:X}", x),
+            Instruction::Font { x } => write!(f, "LD F, V{:X}", x),
+            Instruction::BigFont { x } => write!(f, "LD HF, V{:X}", x),
+            Instruction::Bcd { x } => write!(f, "LD B, V{:X}", x),
+            Instruction::SetPitch { x } => write!(f, "PITCH V{:X}", x),
+            Instruction::StoreRegs { x } => write!(f, "LD [I], V{:X}", x),
+            Instruction::LoadRegs { x } => write!(f, "LD V{:X}, [I]", x),
+            Instruction::SaveFlags { x } => write!(f, "LD R, V{:X}", x),
+            Instruction::LoadFlags { x } => write!(f, "LD V{:X}, R", x),
+            Instruction::Unknown(opcode) => write!(f, "DW {:#06X}", opcode)
+        }
+    }
+}
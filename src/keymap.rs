@@ -0,0 +1,122 @@
+use std::fmt;
+
+use sdl2::keyboard::{Keycode, Scancode};
+
+// Keypad keys in the order they sit on the COSMAC VIP's keypad, row by row
+pub const KEYPAD_ORDER: [u8; 16] = [0x1, 0x2, 0x3, 0xC, 0x4, 0x5, 0x6, 0xD, 0x7, 0x8, 0x9, 0xE, 0xA, 0x0, 0xB, 0xF];
+
+// Names accepted by KeyMap::layout
+pub const LAYOUT_NAMES: [&str; 4] = ["qwerty", "azerty", "dvorak", "numpad"];
+
+// A host key: by the character it types (keycode), or by its position on
+// the keyboard (scancode) for keys SDL has no keycode for, like AZERTY's é
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HostKey {
+    Key(Keycode),
+    Scan(Scancode)
+}
+
+impl HostKey {
+    // An SDL key name like W, Up or Keypad 5, or scan:<name> for a scancode
+    pub fn from_name(name: &str) -> Result<HostKey, String> {
+        let unknown = || format!("unknown key name '{name}'");
+        // SDL turns a single character into a keycode the bindings can't
+        // represent (and panic on), so only ASCII ones are passed on
+        let guarded = |name: &str| name.chars().count() > 1 || name.is_ascii();
+
+        match name.strip_prefix("scan:") {
+            Some(scan) if guarded(scan) => Scancode::from_name(scan).map(HostKey::Scan).ok_or_else(unknown),
+            None if guarded(name) => Keycode::from_name(name).map(HostKey::Key).ok_or_else(unknown),
+            _ => Err(unknown())
+        }
+    }
+}
+
+impl fmt::Display for HostKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HostKey::Key(key) => write!(f, "{}", key.name()),
+            HostKey::Scan(scan) => write!(f, "scan:{}", scan.name())
+        }
+    }
+}
+
+// Which host keys press which hex keys. A hex key can have any number of
+// host keys, a host key presses at most one hex key.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KeyMap {
+    bindings: Vec<(HostKey, u8)>
+}
+
+impl KeyMap {
+    // The 4x4 block on the left of the keyboard, 1-4 down to Z-V on QWERTY,
+    // or the number pad with each digit on its own key
+    pub fn layout(name: &str) -> Option<KeyMap> {
+        use Keycode::*;
+        let keys: [HostKey; 16] = match name {
+            "qwerty" => [Num1, Num2, Num3, Num4, Q, W, E, R, A, S, D, F, Z, X, C, V].map(HostKey::Key),
+            "azerty" => {
+                // The top row types & é " ' without shift, so it goes by position
+                let mut keys = [Num1, Num2, Num3, Num4, A, Z, E, R, Q, S, D, F, W, X, C, V].map(HostKey::Key);
+                let top = [Scancode::Num1, Scancode::Num2, Scancode::Num3, Scancode::Num4].map(HostKey::Scan);
+                keys[..4].copy_from_slice(&top);
+                keys
+            }
+            "dvorak" => [Num1, Num2, Num3, Num4, Quote, Comma, Period, P, A, O, E, U, Semicolon, Q, J, K].map(HostKey::Key),
+            "numpad" => {
+                // KEYPAD_ORDER is 1 2 3 C / 4 5 6 D / 7 8 9 E / A 0 B F
+                [Kp1, Kp2, Kp3, KpMinus, Kp4, Kp5, Kp6, KpPlus, Kp7, Kp8, Kp9, KpEnter, KpDivide, Kp0, KpMultiply, KpPeriod]
+                    .map(HostKey::Key)
+            }
+            _ => return None
+        };
+        Some(KeyMap { bindings: keys.iter().zip(KEYPAD_ORDER).map(|(&key, hex)| (key, hex)).collect() })
+    }
+
+    // The hex key pressed by a host key event
+    pub fn hex_key(&self, keycode: Option<Keycode>, scancode: Option<Scancode>) -> Option<u8> {
+        self.bindings
+            .iter()
+            .find(|&&(key, _)| match key {
+                HostKey::Key(key) => keycode == Some(key),
+                HostKey::Scan(scan) => scancode == Some(scan)
+            })
+            .map(|&(_, hex)| hex)
+    }
+
+    // Host keys bound to a hex key
+    pub fn keys_for(&self, hex: u8) -> impl Iterator<Item = HostKey> + '_ {
+        self.bindings.iter().filter(move |&&(_, h)| h == hex).map(|&(key, _)| key)
+    }
+
+    // Bind a host key to a hex key, as well as the ones it already has
+    pub fn add(&mut self, key: HostKey, hex: u8) {
+        self.bindings.retain(|&(k, _)| k != key);
+        self.bindings.push((key, hex & 0xF));
+    }
+
+    // Replace a hex key's bindings
+    pub fn set(&mut self, hex: u8, keys: &[HostKey]) {
+        self.bindings.retain(|&(_, h)| h != hex);
+        for &key in keys {
+            self.add(key, hex);
+        }
+    }
+
+    // Config lines that recreate this map, in keypad order
+    pub fn config_lines(&self) -> Vec<String> {
+        KEYPAD_ORDER
+            .iter()
+            .map(|&hex| {
+                let keys: Vec<String> = self.keys_for(hex).map(|key| key.to_string()).collect();
+                format!("key.{:x} = {}", hex, keys.join(", "))
+            })
+            .collect()
+    }
+}
+
+impl Default for KeyMap {
+    fn default() -> KeyMap {
+        KeyMap::layout("qwerty").unwrap()
+    }
+}
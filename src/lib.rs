@@ -0,0 +1,28 @@
+// Core CHIP-8 interpreter, independent of any frontend.
+// The SDL2 frontend lives in main.rs behind the `sdl` feature.
+
+pub mod assembler;
+pub mod config;
+pub mod cpu;
+mod crc32;
+pub mod debugger;
+pub mod disasm;
+pub mod error;
+pub mod gamepad;
+pub mod headless;
+pub mod instruction;
+mod json;
+pub mod movie;
+pub mod quirks;
+pub mod rewind;
+pub mod rng;
+pub mod romdb;
+mod sha1;
+pub mod trace;
+
+pub use cpu::{AccessKind, Chip8, Chip8Builder, ExecutionEffect, FrameResult, MemoryAccess, BIG_FONT, DISPLAY_HEIGHT, DISPLAY_WIDTH, FONT};
+pub use error::{Chip8Error, StateError};
+pub use instruction::{decode, Instruction};
+pub use quirks::{MemoryIncrement, Quirks};
+pub use rng::RandomMode;
+pub use trace::{TraceLevel, Tracer};
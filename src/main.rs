@@ -1,35 +1,139 @@
 extern crate sdl2;
 
-mod cpu;
+mod cli;
+mod console;
+mod keymap;
 mod sound;
 
+use std::collections::VecDeque;
 use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
 use std::time::{Duration, Instant};
-use std::{fs::File, io::Read};
 
 use sdl2::audio::AudioSpecDesired;
+use sdl2::controller::{self, GameController};
 use sdl2::event::Event;
-use sdl2::keyboard::Keycode;
+use sdl2::keyboard::{Keycode, Mod};
 use sdl2::pixels::Color;
 use sdl2::rect::Rect;
 use sdl2::render::Canvas;
 use sdl2::video::Window;
 
+use chip8::config::{self, Config};
+use chip8::debugger::{format_listing, parse_command, Command, Debugger};
+use chip8::gamepad::{self, Gamepads, PadInput, PadMap};
+use chip8::headless::{display_ascii, Limit};
+use chip8::movie::Movie;
+use chip8::rewind::Rewind;
+use chip8::romdb::{RomDatabase, RomInfo};
+use chip8::{Chip8, Tracer};
+
+use keymap::{HostKey, KeyMap, KEYPAD_ORDER};
+
 const WIDTH: usize = 64;
 const HEIGHT: usize = 32;
-const SCALE: usize = 20;
-const IPS: u64 = 700; // instructions per second
-const COLOR_1: Color = Color::WHITE;
-const COLOR_2: Color = Color::BLACK;
+const FRAME_TIME: Duration = Duration::from_nanos(1_000_000_000 / 60);
+const UNDO_FRAMES: usize = 60 * 60; // frames of instructions the debugger can step back
+const REWIND_FRAMES: usize = 60 * 10; // seconds of gameplay Backspace can rewind
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
-    let fname = &args[1];
-    println!("fname: {fname}");
+    let args: Vec<String> = env::args().skip(1).collect();
+    let args = match cli::parse_args(&args) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("chip8: {e}\nRun 'chip8 --help' for the options.");
+            process::exit(2);
+        }
+    };
+
+
+    let rom_path = &args.rom;
+    let rom_name = rom_path.file_name().unwrap_or_default().to_string_lossy();
+    let file_buffer = fs::read(rom_path).unwrap_or_else(|e| fail(&format!("Error reading {}: {e}", rom_path.display())));
+    println!("{rom_name}: sha1 {}", config::rom_hash(&file_buffer));
+
+    // Settings from the config file, then the command line
+    let config_path = args.config.clone().or_else(config::default_path).unwrap_or_default();
+    if args.config.is_some() && !config_path.exists() {
+        fail(&format!("Config file not found: {}", config_path.display()));
+    }
+    let config = if args.no_config { Config::default() } else { Config::load(&config_path).unwrap_or_else(|e| fail(&e)) };
+
+    // What the ROM database knows about it: the built-in entries, and those of
+    // a programs.json next to the config file, like the chip-8-database's
+    let mut database = RomDatabase::builtin();
+    let database_path = config_path.with_file_name("programs.json");
+    if !args.no_config && !config_path.as_os_str().is_empty() && database_path.exists() {
+        match fs::read_to_string(&database_path).map_err(|e| e.to_string()).and_then(|text| RomDatabase::parse(&text)) {
+            Ok(entries) => database.merge(entries),
+            Err(e) => eprintln!("Warning: ignoring {}: {e}", database_path.display())
+        }
+    }
+    let info = database.lookup(&file_buffer);
+
+    let options = cli::options(&args, &config, &config_path, &file_buffer, info).unwrap_or_else(|e| fail(&format!("chip8: {e}")));
+    let title = info.map_or(rom_name.to_string(), |info| info.title.clone());
+    if let Some(info) = info {
+        print_rom_info(info, &options.keymap, &options.padmap);
+    }
+
+    // Input movie to record or replay. Keys only change between frames, so
+    // a replay sees them at the same point of the run.
+    let mut recording = None;
+    let mut replay = VecDeque::new();
+    let mut cycles_per_frame = options.cycles_per_frame;
+    let movie_active = options.record.is_some() || options.play.is_some();
+
+    let mut chip8 = if let Some(path) = &options.play {
+        let movie = fs::read_to_string(path)
+            .map_err(|e| e.to_string())
+            .and_then(|text| Movie::parse(&text))
+            .unwrap_or_else(|e| fail(&format!("Error reading movie {path}: {e}")));
+        cycles_per_frame = movie.cycles_per_frame;
+        replay.extend(movie.events.iter().copied());
+        movie.machine(&file_buffer).map_err(|e| format!("{path} {e}"))
+    } else {
+        let mut chip8 = Chip8::new(options.quirks);
+        chip8.init();
+        chip8.set_random_mode(options.random_mode);
+
+        // A recording needs a known seed to replay
+        let seed = if options.record.is_some() { Some(options.seed.unwrap_or_else(rand::random)) } else { options.seed };
+        if let Some(seed) = seed {
+            chip8.set_seed(seed);
+        }
+        if let Some(path) = &options.record {
+            let movie = Movie::new(&file_buffer, seed.unwrap(), options.random_mode, cycles_per_frame, options.quirks);
+            recording = Some((movie, path));
+        }
+        chip8.load_bin(&file_buffer).map(|_| chip8).map_err(|e| e.to_string())
+    }
+    .unwrap_or_else(|e| fail(&format!("Error loading {}: {e}", rom_path.display())));
+
+    if let Some(path) = &options.trace {
+        let mut tracer = Tracer::to_file(options.trace_level, path).unwrap_or_else(|e| fail(&format!("Error creating trace {path}: {e}")));
+        tracer.set_addresses(options.trace_addresses.clone());
+        tracer.set_classes(options.trace_classes);
+        chip8.set_tracer(Some(tracer));
+    }
+
+    if options.headless {
+        let keys: Vec<_> = replay.into();
+        let result = chip8::headless::run(&mut chip8, Limit::Frames(options.frames), cycles_per_frame, &keys);
+        print!("{}", display_ascii(&chip8));
+        if let Some(e) = result.error {
+            if let Some(tracer) = chip8.tracer_mut() {
+                let _ = tracer.flush();
+            }
+            fail(&format!("Error: {e}"));
+        }
+        return;
+    }
 
     let sdl_context = sdl2::init().unwrap();
     let video_subsystem = sdl_context.video().unwrap();
-    let timer = sdl_context.timer().unwrap();
     let audio = sdl_context.audio().unwrap();
 
     let desired_spec = AudioSpecDesired {
@@ -38,61 +142,155 @@ fn main() {
         samples: Some(1024),
     };
 
-    let audio_device = audio
+    let mut audio_device = audio
         .open_playback(None, &desired_spec, |spec| sound::SquareWave {
             phase: 0.0,
             phase_increment: 440.0 / spec.freq as f32,
             volume: 0.25,
+            pattern: None,
+            pattern_position: 0.0,
+            pattern_increment: 0.0,
         })
         .unwrap();
 
-    let window = video_subsystem
-        .window(
-            &format!("chip8 | {fname}"),
-            WIDTH as u32 * SCALE as u32,
-            HEIGHT as u32 * SCALE as u32,
-        )
-        .position_centered()
-        .build()
-        .unwrap();
+    let mut window = video_subsystem.window(
+        &format!("chip8 | {title}"),
+        WIDTH as u32 * options.scale,
+        HEIGHT as u32 * options.scale,
+    );
+    window.position_centered();
+    if options.fullscreen {
+        window.fullscreen_desktop();
+    }
+    let window = window.build().unwrap_or_else(|e| fail(&format!("Error opening window: {e}")));
 
     let mut canvas = window.into_canvas().build().unwrap();
+    let palette = options.palette;
 
-    canvas.set_draw_color(COLOR_2);
+    canvas.set_draw_color(palette[0]);
     canvas.clear();
     canvas.present();
 
     let mut event_pump = sdl_context.event_pump().unwrap();
 
-    let gap_time = Duration::from_millis(1000 / IPS);
-    let mut last_cycle = Instant::now();
-    let mut last_timer_tick = 0;
-    let mut paused = false;
+    // Controllers are opened as SDL reports them, including those already
+    // plugged in at startup
+    let controller_subsystem = sdl_context.game_controller().map_err(|e| eprintln!("Warning: no controller support: {e}")).ok();
+    let mut controllers: Vec<GameController> = Vec::new();
+    let mut gamepads = Gamepads::new(options.padmap.clone());
 
-    let mut chip8 = cpu::Chip8::new(true);
-    chip8.init();
+    // F5 walks through the keypad asking for a host key for each hex key
+    let mut keymap = options.keymap.clone();
+    let mut rebinding: Option<usize> = None; // index into KEYPAD_ORDER
+    let mut keymap_before_rebinding = keymap.clone(); // put back if it is cancelled
 
-    // let test_bin: [u8; 10] = [
-    //     0x00, 0xE0, // Clear screen
-    //     0x60, 0x05, // Set V0 to 05
-    //     0x71, 0x10, // Add 10 to V1
-    //     0xA0, 0x02, // Set i to 002
-    //     0x12, 0x00 // Jump to 200
-    // ];
+    let mut rewind = Rewind::new(REWIND_FRAMES);
+    rewind.push(&chip8);
+    let mut rewinding = false;
 
-    // chip8.load_bin(&test_bin);
+    let mut frame: u64 = 0; // completed frames, keys pressed now land on this one
+    let mut next_frame = Instant::now();
 
-    let mut file = File::open(&format!("binaries/{fname}")).expect("Error opening file.");
-    let mut file_buffer = Vec::new();
-    file.read_to_end(&mut file_buffer)
-        .expect("Error reading file.");
-    chip8.load_bin(&file_buffer);
+    // Step debugger, driven from a console prompt
+    let mut debugger = if options.debug {
+        println!("Debugger: type 'help' for commands");
+        println!("{}", format_listing(&chip8, 1));
+        console::prompt();
+        chip8.set_access_logging(true);
+        chip8.set_undo_recording(UNDO_FRAMES * cycles_per_frame as usize);
+        Some((Debugger::new(), console::spawn()))
+    } else {
+        None
+    };
 
     'main: loop {
+        if let Some((debugger, commands)) = debugger.as_mut() {
+            while let Ok(line) = commands.try_recv() {
+                match parse_command(&line) {
+                    Ok(command) => {
+                        let output = debugger.command(command, &mut chip8);
+                        if !output.is_empty() {
+                            println!("{output}");
+                        }
+                        // Stepping back can change the screen
+                        if let Command::StepBack(_) | Command::ReverseContinue(_) = command {
+                            render(&mut canvas, &chip8, &palette).unwrap();
+                        }
+                    }
+                    Err(e) => println!("{e}")
+                }
+                if debugger.is_paused() {
+                    console::prompt();
+                }
+            }
+        }
+
         for event in event_pump.poll_iter() {
             match event {
-                Event::Quit { .. }
-                | Event::KeyDown {
+                Event::Quit { .. } => {
+                    println!("Quitting app");
+                    break 'main;
+                }
+
+                // While rebinding every key press goes to the keypad key asked for,
+                // and a held key's repeats go nowhere
+                Event::KeyDown {
+                    keycode,
+                    scancode,
+                    repeat,
+                    ..
+                } if rebinding.is_some() => {
+                    if repeat {
+                        continue;
+                    }
+                    let index = rebinding.unwrap();
+                    if keycode == Some(Keycode::Escape) {
+                        println!("Rebinding cancelled");
+                        keymap = keymap_before_rebinding.clone();
+                        rebinding = None;
+                    } else if keycode.is_some_and(|key| state_slot(key).is_some() || [Keycode::F5, Keycode::Backspace].contains(&key)) {
+                        println!("{} is taken, press another key", keycode.unwrap());
+                        continue;
+                    } else if let Some(key) = keycode.map(HostKey::Key).or(scancode.map(HostKey::Scan)) {
+                        keymap.set(KEYPAD_ORDER[index], &[key]);
+                        rebinding = Some(index + 1).filter(|&i| i < KEYPAD_ORDER.len());
+                        if rebinding.is_none() {
+                            println!("Keys rebound, to keep them add these to {}:", config_path.display());
+                            for line in keymap.config_lines() {
+                                println!("  {line}");
+                            }
+                        }
+                    }
+                    match rebinding {
+                        Some(index) => {
+                            let prompt = format!("Press a key for keypad {:X}", KEYPAD_ORDER[index]);
+                            println!("{prompt}");
+                            let _ = canvas.window_mut().set_title(&format!("chip8 | {prompt} (Escape cancels)"));
+                        }
+                        None => {
+                            let _ = canvas.window_mut().set_title(&format!("chip8 | {title}"));
+                        }
+                    }
+                }
+
+                Event::KeyDown {
+                    keycode: Some(Keycode::F5),
+                    ..
+                } => {
+                    // Let go of held keys, the game won't see their releases otherwise
+                    for hex in 0..16 {
+                        if chip8.key(hex) && options.play.is_none() {
+                            press_key(&mut chip8, &mut recording, frame, hex, false);
+                        }
+                    }
+                    let prompt = format!("Press a key for keypad {:X}", KEYPAD_ORDER[0]);
+                    println!("Rebinding the keypad, Escape cancels\n{prompt}");
+                    let _ = canvas.window_mut().set_title(&format!("chip8 | {prompt} (Escape cancels)"));
+                    keymap_before_rebinding = keymap.clone();
+                    rebinding = Some(0);
+                }
+
+                Event::KeyDown {
                     keycode: Some(Keycode::Escape),
                     ..
                 } => {
@@ -100,21 +298,110 @@ fn main() {
                     break 'main;
                 }
 
+                // F1-F4 load a save state slot, Shift+F1-F4 save to it
                 Event::KeyDown {
-                    keycode: Some(key), ..
+                    keycode: Some(key),
+                    keymod,
+                    ..
+                } if state_slot(key).is_some() => {
+                    let path = state_path(rom_path, state_slot(key).unwrap());
+                    if keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD) {
+                        match fs::write(&path, chip8.save_state()) {
+                            Ok(()) => println!("Saved state to {}", path.display()),
+                            Err(e) => eprintln!("Error saving {}: {e}", path.display())
+                        }
+                    } else if movie_active {
+                        println!("Loading states is off while recording or replaying a movie");
+                    } else {
+                        match fs::read(&path).map_err(|e| e.to_string())
+                            .and_then(|state| chip8.load_state(&state).map_err(|e| e.to_string()))
+                        {
+                            Ok(()) => {
+                                println!("Loaded state from {}", path.display());
+                                render(&mut canvas, &chip8, &palette).unwrap();
+                                rewind.clear();
+                                rewind.push(&chip8);
+                            }
+                            Err(e) => eprintln!("Error loading {}: {e}", path.display())
+                        }
+                    }
+                }
+
+                // Hold Backspace to rewind
+                Event::KeyDown {
+                    keycode: Some(Keycode::Backspace),
+                    ..
                 } => {
-                    chip8.key_down(key);
-                    if paused {
-                        paused = false;
+                    if movie_active {
+                        println!("Rewinding is off while recording or replaying a movie");
+                    } else {
+                        rewinding = true;
                     }
                 }
 
                 Event::KeyUp {
-                    keycode: Some(key), ..
+                    keycode: Some(Keycode::Backspace),
+                    ..
+                } => rewinding = false,
+
+                // Key repeats are left out, the keypad has no use for them
+                Event::KeyDown {
+                    keycode,
+                    scancode,
+                    repeat: false,
+                    ..
+                }
+                | Event::KeyUp {
+                    keycode,
+                    scancode,
+                    repeat: false,
+                    ..
                 } => {
-                    chip8.key_up(key);
-                    if paused {
-                        paused = false;
+                    let pressed = matches!(event, Event::KeyDown { .. });
+                    match keymap.hex_key(keycode, scancode) {
+                        Some(_) if options.play.is_some() => {}
+                        Some(hex) => press_key(&mut chip8, &mut recording, frame, hex, pressed),
+                        None => {}
+                    }
+                }
+
+                Event::ControllerDeviceAdded { which, .. } => {
+                    if let Some(subsystem) = &controller_subsystem {
+                        match subsystem.open(which) {
+                            Ok(controller) => {
+                                println!("Controller connected: {}", controller.name());
+                                controllers.push(controller);
+                            }
+                            Err(e) => eprintln!("Error opening controller {which}: {e}")
+                        }
+                    }
+                }
+
+                Event::ControllerDeviceRemoved { which, .. } => {
+                    controllers.retain(|controller| controller.instance_id() != which);
+                    for (hex, pressed) in gamepads.disconnect(which) {
+                        if options.play.is_none() {
+                            press_key(&mut chip8, &mut recording, frame, hex, pressed);
+                        }
+                    }
+                }
+
+                Event::ControllerButtonDown { which, button, .. } | Event::ControllerButtonUp { which, button, .. } => {
+                    let pressed = matches!(event, Event::ControllerButtonDown { .. });
+                    let input = pad_button(button).map(|button| PadInput::Button(button, pressed));
+                    for (hex, pressed) in input.map(|input| gamepads.input(which, input)).unwrap_or_default() {
+                        if options.play.is_none() {
+                            press_key(&mut chip8, &mut recording, frame, hex, pressed);
+                        }
+                    }
+                }
+
+                Event::ControllerAxisMotion { which, axis, value, .. } => {
+                    let input = pad_axis(axis).map(|axis| PadInput::Axis(axis, value));
+                    for (hex, pressed) in input.map(|input| gamepads.input(which, input)).unwrap_or_default() {
+                        if options.play.is_none() {
+                            press_key(&mut chip8, &mut recording, frame, hex, pressed);
+                        }
                     }
                 }
 
@@ -122,59 +409,192 @@ fn main() {
             }
         }
 
-        let curr_timer = timer.ticks();
-        if curr_timer - last_timer_tick >= 1000 / 60 {
-            chip8.decrement_timers();
-            if chip8.sound_timer() == 0 {
-                audio_device.pause();
-            }
-            last_timer_tick = curr_timer;
+        // Frames run at 60 Hz, sleep until the next one is due
+        let now = Instant::now();
+        if now < next_frame {
+            std::thread::sleep(next_frame - now);
+            continue;
         }
+        // After falling behind (say the window was dragged) carry on from now
+        // instead of running a burst of frames to catch up
+        next_frame = (next_frame + FRAME_TIME).max(now);
 
-        if !paused && last_cycle.elapsed() >= gap_time {
-            last_cycle = Instant::now();
-            let op = chip8.fetch();
-            match chip8.execute(op) {
-                cpu::ExecutionEffect::NoEffect => {}
+        if rebinding.is_some() {
+            audio_device.pause();
+            continue;
+        }
+        if rewinding {
+            // One frame back per tick
+            if rewind.rewind(&mut chip8) {
+                render(&mut canvas, &chip8, &palette).unwrap();
+            }
+            audio_device.pause();
+            continue;
+        }
 
-                cpu::ExecutionEffect::DisplayUpdate => {
-                    let display = chip8.display();
-                    render(&mut canvas, display).unwrap();
-                }
+        while let Some(event) = replay.pop_front_if(|e| e.frame <= frame) {
+            chip8.set_key(event.key, event.pressed);
+        }
 
-                cpu::ExecutionEffect::JumpToSelf => {
-                    println!("Jump to self: pausing execution");
-                    paused = true;
+        let result = match debugger.as_mut() {
+            // Stop before the next instruction when the debugger says so
+            Some((debugger, _)) => chip8.run_frame_until(cycles_per_frame, |chip8| {
+                let was_paused = debugger.is_paused();
+                let stop = debugger.should_break(chip8);
+                if stop && !was_paused {
+                    if let Some(reason) = debugger.take_reason() {
+                        println!("{reason}");
+                    }
+                    println!("{}", format_listing(chip8, 1));
+                    console::prompt();
                 }
+                stop
+            }),
+            None => chip8.run_frame(cycles_per_frame)
+        };
 
-                cpu::ExecutionEffect::WaitingForKey => {
-                    println!("Waiting for key press: pausing execution");
-                    paused = true;
-                }
+        if result.display_updated {
+            render(&mut canvas, &chip8, &palette).unwrap();
+        }
+        if result.sound {
+            // Pick up the XO-CHIP pattern and pitch, if the program set one
+            if let Some(&pattern) = chip8.audio_pattern() {
+                let mut wave = audio_device.lock();
+                wave.pattern = Some(pattern);
+                wave.pattern_increment = chip8.playback_rate() / desired_spec.freq.unwrap() as f32;
+            }
+            if !options.mute {
+                audio_device.resume();
+            }
+        }
+        if chip8.sound_timer() == 0 {
+            audio_device.pause();
+        }
+        // Snapshots are taken between whole frames, so a paused debugger
+        // doesn't fill the buffer with copies of the same one
+        if result.completed() {
+            frame += 1;
+            rewind.push(&chip8);
+        }
 
-                cpu::ExecutionEffect::Sound => {
-                    audio_device.resume();
+        if let Some(e) = result.error {
+            eprintln!("Error: {e}");
+            match debugger.as_mut() {
+                // Keep the machine around for inspection
+                Some((debugger, _)) => {
+                    debugger.pause();
+                    console::prompt();
                 }
+                None => break 'main
             }
         }
+        if result.exited {
+            println!("Program exited");
+            break 'main;
+        }
+    }
+
+    if let Some((movie, path)) = recording {
+        match fs::write(path, movie.to_string()) {
+            Ok(()) => println!("Saved movie to {path}"),
+            Err(e) => eprintln!("Error saving movie {path}: {e}")
+        }
+    }
+}
+
+// Press or release a keypad key, and note it in the movie being recorded
+fn press_key(chip8: &mut Chip8, recording: &mut Option<(Movie, &String)>, frame: u64, hex: u8, pressed: bool) {
+    chip8.set_key(hex, pressed);
+    if let Some((movie, _)) = recording.as_mut() {
+        movie.record(frame, hex, pressed);
+    }
+}
+
+fn pad_button(button: controller::Button) -> Option<gamepad::Button> {
+    use controller::Button::*;
+    Some(match button {
+        DPadUp => gamepad::Button::DPadUp,
+        DPadDown => gamepad::Button::DPadDown,
+        DPadLeft => gamepad::Button::DPadLeft,
+        DPadRight => gamepad::Button::DPadRight,
+        A => gamepad::Button::A,
+        B => gamepad::Button::B,
+        X => gamepad::Button::X,
+        Y => gamepad::Button::Y,
+        LeftShoulder => gamepad::Button::LeftShoulder,
+        RightShoulder => gamepad::Button::RightShoulder,
+        Back => gamepad::Button::Back,
+        Start => gamepad::Button::Start,
+        _ => return None
+    })
+}
+
+fn pad_axis(axis: controller::Axis) -> Option<gamepad::Axis> {
+    match axis {
+        controller::Axis::LeftX => Some(gamepad::Axis::LeftX),
+        controller::Axis::LeftY => Some(gamepad::Axis::LeftY),
+        controller::Axis::TriggerLeft => Some(gamepad::Axis::TriggerLeft),
+        controller::Axis::TriggerRight => Some(gamepad::Axis::TriggerRight),
+        _ => None
+    }
+}
+
+// Title, authors and what the keys do, from the ROM database
+fn print_rom_info(info: &RomInfo, keymap: &KeyMap, padmap: &PadMap) {
+    let platform = info.platform.as_deref().map(|p| format!(" [{p}]")).unwrap_or_default();
+    if info.authors.is_empty() {
+        println!("{}{platform}", info.title);
+    } else {
+        println!("{} by {}{platform}", info.title, info.authors.join(", "));
+    }
+
+    for (action, hex) in &info.keys {
+        let host: Vec<String> =
+            keymap.keys_for(*hex).map(|key| key.to_string()).chain(padmap.buttons_for(*hex).map(|button| button.name().to_string())).collect();
+        println!("  {action}: {hex:X} ({})", host.join(", "));
+    }
+}
+
+// Save state slot for a function key
+fn state_slot(key: Keycode) -> Option<u8> {
+    match key {
+        Keycode::F1 => Some(1),
+        Keycode::F2 => Some(2),
+        Keycode::F3 => Some(3),
+        Keycode::F4 => Some(4),
+        _ => None
     }
 }
 
-fn render(canvas: &mut Canvas<Window>, display: &[u8; WIDTH * HEIGHT]) -> Result<(), String> {
-    canvas.set_draw_color(COLOR_2);
+// Slots are kept next to the ROM: pong.ch8 -> pong.state1
+fn state_path(rom_path: &Path, slot: u8) -> PathBuf {
+    rom_path.with_extension(format!("state{slot}"))
+}
+
+fn render(canvas: &mut Canvas<Window>, chip8: &Chip8, palette: &[Color; 4]) -> Result<(), String> {
+    canvas.set_draw_color(palette[0]);
     canvas.clear();
-    canvas.set_draw_color(COLOR_1);
 
-    for y in 0..HEIGHT {
-        for x in 0..WIDTH {
-            let px = display[y * WIDTH + x];
+    // Whole pixels as large as fit the window, centered
+    let display = chip8.display();
+    let (width, height) = chip8.resolution();
+    let (window_width, window_height) = canvas.output_size()?;
+    let scale = (window_width as usize / width).min(window_height as usize / height).max(1);
+    let left = (window_width as usize).saturating_sub(width * scale) / 2;
+    let top = (window_height as usize).saturating_sub(height * scale) / 2;
+
+    for y in 0..height {
+        for x in 0..width {
+            let px = display[y * width + x];
             if px != 0 {
+                canvas.set_draw_color(palette[px as usize & 3]);
+
                 // Draw the pixel as a scaled rectangle
                 let rect = Rect::new(
-                    (x * SCALE) as i32,
-                    (y * SCALE) as i32,
-                    SCALE as u32,
-                    SCALE as u32,
+                    (left + x * scale) as i32,
+                    (top + y * scale) as i32,
+                    scale as u32,
+                    scale as u32,
                 );
                 canvas.fill_rect(rect)?;
             }
@@ -185,3 +605,7 @@ fn render(canvas: &mut Canvas<Window>, display: &[u8; WIDTH * HEIGHT]) -> Result
     Ok(())
 }
 
+fn fail(message: &str) -> ! {
+    eprintln!("{message}");
+    process::exit(1);
+}
@@ -0,0 +1,166 @@
+use std::fmt;
+
+use crate::cpu::Chip8;
+use crate::headless::{parse_key_script, KeyEvent};
+use crate::quirks::{MemoryIncrement, Quirks};
+use crate::rng::RandomMode;
+use crate::sha1::sha1_hex;
+
+// Input movie: every keypad change with the frame it happened on, plus what
+// else decides how a run goes (seed, quirks, speed), so a session can be
+// replayed exactly. Stored as text, the events in the key script format:
+//
+//     chip8-movie 2
+//     rom 2f5b7c0a9e4d1c3b8a6f0e2d4c6b8a0f1e3d5c7b
+//     seed 42
+//     random xorshift
+//     cycles-per-frame 12
+//     quirks shift_uses_vy=0 jump_uses_vx=1 memory_increment=none vf_reset=0 ...
+//     events
+//     120 down 5
+//     126 up 5
+const HEADER: &str = "chip8-movie 2";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Movie {
+    pub rom_sha1: String, // SHA-1 of the ROM it was recorded on, in hex
+    pub seed: u64,
+    pub random_mode: RandomMode,
+    pub cycles_per_frame: u64,
+    pub quirks: Quirks,
+    pub events: Vec<KeyEvent> // in frame order
+}
+
+impl Movie {
+    // An empty movie for a run of rom
+    pub fn new(rom: &[u8], seed: u64, random_mode: RandomMode, cycles_per_frame: u64, quirks: Quirks) -> Movie {
+        Movie {
+            rom_sha1: sha1_hex(rom),
+            seed,
+            random_mode,
+            cycles_per_frame,
+            quirks,
+            events: Vec::new()
+        }
+    }
+
+    pub fn matches_rom(&self, rom: &[u8]) -> bool {
+        sha1_hex(rom) == self.rom_sha1
+    }
+
+    pub fn record(&mut self, frame: u64, key: u8, pressed: bool) {
+        self.events.push(KeyEvent { frame, key, pressed });
+    }
+
+    // A machine set up like the recorded one, with the ROM loaded. Another
+    // ROM would not replay the same, so it is refused.
+    pub fn machine(&self, rom: &[u8]) -> Result<Chip8, String> {
+        if !self.matches_rom(rom) {
+            return Err(format!("recorded with another ROM (SHA-1 {})", self.rom_sha1));
+        }
+        let mut chip8 = Chip8::new(self.quirks);
+        chip8.init();
+        chip8.set_seed(self.seed);
+        chip8.set_random_mode(self.random_mode);
+        chip8.load_bin(rom).map_err(|e| e.to_string())?;
+        Ok(chip8)
+    }
+
+    pub fn parse(text: &str) -> Result<Movie, String> {
+        let mut lines = text.lines();
+        match lines.next().map(str::trim) {
+            Some(HEADER) => {}
+            // Version 1 named the ROM by CRC-32, which can't be checked against its SHA-1
+            Some("chip8-movie 1") => return Err("movie from an older version, record it again".to_string()),
+            _ => return Err(format!("not a movie file, expected '{}'", HEADER))
+        }
+
+        let mut rom_sha1 = None;
+        let mut seed = None;
+        let mut random_mode = None;
+        let mut cycles_per_frame = None;
+        let mut quirks = None;
+
+        for line in lines.by_ref() {
+            let line = line.trim();
+            if line == "events" {
+                break;
+            }
+            let (name, value) = line.split_once(' ').unwrap_or((line, ""));
+            match name {
+                "rom" if value.len() == 40 && value.bytes().all(|b| b.is_ascii_hexdigit()) => rom_sha1 = Some(value.to_ascii_lowercase()),
+                "rom" => return Err(format!("invalid ROM SHA-1 '{}'", value)),
+                "seed" => seed = value.parse().ok(),
+                "random" => {
+                    random_mode = Some(match value {
+                        "xorshift" => RandomMode::Xorshift,
+                        "vip" => RandomMode::CosmacVip,
+                        _ => return Err(format!("unknown random mode '{}'", value))
+                    })
+                }
+                "cycles-per-frame" => cycles_per_frame = value.parse().ok(),
+                "quirks" => quirks = Some(parse_quirks(value)?),
+                "" => {}
+                other => return Err(format!("unknown movie field '{}'", other))
+            }
+        }
+
+        // The rest is a key script
+        let events = parse_key_script(&lines.collect::<Vec<_>>().join("\n")).map_err(|e| format!("events: {}", e))?;
+
+        Ok(Movie {
+            rom_sha1: rom_sha1.ok_or("missing 'rom'")?,
+            seed: seed.ok_or("missing or invalid 'seed'")?,
+            random_mode: random_mode.ok_or("missing 'random'")?,
+            cycles_per_frame: cycles_per_frame.ok_or("missing or invalid 'cycles-per-frame'")?,
+            quirks: quirks.ok_or("missing 'quirks'")?,
+            events
+        })
+    }
+}
+
+fn parse_quirks(text: &str) -> Result<Quirks, String> {
+    let mut quirks = Quirks::default();
+    for field in text.split_whitespace() {
+        let (name, value) = field.split_once('=').ok_or(format!("invalid quirk '{}'", field))?;
+        quirks.set(name, value)?;
+    }
+    Ok(quirks)
+}
+
+impl fmt::Display for Movie {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let q = &self.quirks;
+        let memory_increment = match q.memory_increment {
+            MemoryIncrement::None => "none",
+            MemoryIncrement::X => "x",
+            MemoryIncrement::XPlusOne => "x+1"
+        };
+        let random = match self.random_mode {
+            RandomMode::Xorshift => "xorshift",
+            RandomMode::CosmacVip => "vip"
+        };
+
+        writeln!(f, "{}", HEADER)?;
+        writeln!(f, "rom {}", self.rom_sha1)?;
+        writeln!(f, "seed {}", self.seed)?;
+        writeln!(f, "random {}", random)?;
+        writeln!(f, "cycles-per-frame {}", self.cycles_per_frame)?;
+        writeln!(
+            f,
+            "quirks shift_uses_vy={} jump_uses_vx={} memory_increment={} vf_reset={} clip_sprites={} display_wait={} extended_memory={}",
+            q.shift_uses_vy as u8,
+            q.jump_uses_vx as u8,
+            memory_increment,
+            q.vf_reset as u8,
+            q.clip_sprites as u8,
+            q.display_wait as u8,
+            q.extended_memory as u8
+        )?;
+        writeln!(f, "events")?;
+        for event in &self.events {
+            writeln!(f, "{} {} {:x}", event.frame, if event.pressed { "down" } else { "up" }, event.key)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,129 @@
+// Behaviour of instructions that differ between CHIP-8 implementations.
+// Each field toggles one ambiguity independently; the presets below match
+// the platforms ROMs were commonly written for.
+
+// How FX55 / FX65 change i after storing / loading registers
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MemoryIncrement {
+    None, // i is left unchanged (SUPER-CHIP 1.1)
+    X, // i += X (CHIP-48, SUPER-CHIP 1.0)
+    XPlusOne // i += X + 1 (COSMAC VIP, XO-CHIP)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Quirks {
+    pub shift_uses_vy: bool, // 8XY6 / 8XYE: set v[X] = v[Y] before shifting
+    pub jump_uses_vx: bool, // BNNN is BXNN: jump to XNN + v[X] instead of NNN + v[0]
+    pub memory_increment: MemoryIncrement, // FX55 / FX65 side effect on i
+    pub vf_reset: bool, // 8XY1 / 8XY2 / 8XY3: reset v[F] to 0
+    pub clip_sprites: bool, // DXYN: clip pixels at the screen edge instead of wrapping them
+    pub display_wait: bool, // DXYN: wait for the next 60 Hz tick, at most one draw per frame
+    pub extended_memory: bool // 64 KiB of memory instead of 4 KiB (XO-CHIP)
+}
+
+impl Quirks {
+    pub const COSMAC_VIP: Quirks = Quirks {
+        shift_uses_vy: true,
+        jump_uses_vx: false,
+        memory_increment: MemoryIncrement::XPlusOne,
+        vf_reset: true,
+        clip_sprites: true,
+        display_wait: true,
+        extended_memory: false
+    };
+
+    pub const CHIP_48: Quirks = Quirks {
+        shift_uses_vy: false,
+        jump_uses_vx: true,
+        memory_increment: MemoryIncrement::X,
+        vf_reset: false,
+        clip_sprites: true,
+        display_wait: false,
+        extended_memory: false
+    };
+
+    pub const SUPER_CHIP_1_0: Quirks = Quirks {
+        shift_uses_vy: false,
+        jump_uses_vx: true,
+        memory_increment: MemoryIncrement::X,
+        vf_reset: false,
+        clip_sprites: true,
+        display_wait: false,
+        extended_memory: false
+    };
+
+    pub const SUPER_CHIP_1_1: Quirks = Quirks {
+        shift_uses_vy: false,
+        jump_uses_vx: true,
+        memory_increment: MemoryIncrement::None,
+        vf_reset: false,
+        clip_sprites: true,
+        display_wait: false,
+        extended_memory: false
+    };
+
+    pub const XO_CHIP: Quirks = Quirks {
+        shift_uses_vy: true,
+        jump_uses_vx: false,
+        memory_increment: MemoryIncrement::XPlusOne,
+        vf_reset: false,
+        clip_sprites: false,
+        display_wait: false,
+        extended_memory: true
+    };
+
+    // Names accepted by from_name, for command line options
+    pub const PRESET_NAMES: [&'static str; 5] = ["vip", "chip48", "schip1.0", "schip1.1", "xochip"];
+
+    // Look up a preset by name
+    pub fn from_name(name: &str) -> Option<Quirks> {
+        match name.to_lowercase().as_str() {
+            "vip" | "chip8" => Some(Quirks::COSMAC_VIP),
+            "chip48" => Some(Quirks::CHIP_48),
+            "schip1.0" => Some(Quirks::SUPER_CHIP_1_0),
+            "schip1.1" | "schip" => Some(Quirks::SUPER_CHIP_1_1),
+            "xochip" | "xo" => Some(Quirks::XO_CHIP),
+            _ => None
+        }
+    }
+
+    // Field names accepted by set
+    pub const FIELD_NAMES: [&'static str; 7] =
+        ["shift_uses_vy", "jump_uses_vx", "memory_increment", "vf_reset", "clip_sprites", "display_wait", "extended_memory"];
+
+    // Set one quirk by field name, as written in movies and the config file:
+    // 0 / 1 (or false / true) for flags, none / x / x+1 for memory_increment
+    pub fn set(&mut self, name: &str, value: &str) -> Result<(), String> {
+        let flag = match value {
+            "0" | "false" => Some(false),
+            "1" | "true" => Some(true),
+            _ => None
+        };
+        let flag = || flag.ok_or(format!("invalid value for {}: '{}'", name, value));
+
+        match name {
+            "shift_uses_vy" => self.shift_uses_vy = flag()?,
+            "jump_uses_vx" => self.jump_uses_vx = flag()?,
+            "memory_increment" => {
+                self.memory_increment = match value {
+                    "none" => MemoryIncrement::None,
+                    "x" => MemoryIncrement::X,
+                    "x+1" => MemoryIncrement::XPlusOne,
+                    _ => return Err(format!("invalid value for memory_increment: '{}'", value))
+                }
+            }
+            "vf_reset" => self.vf_reset = flag()?,
+            "clip_sprites" => self.clip_sprites = flag()?,
+            "display_wait" => self.display_wait = flag()?,
+            "extended_memory" => self.extended_memory = flag()?,
+            _ => return Err(format!("unknown quirk '{}'", name))
+        }
+        Ok(())
+    }
+}
+
+impl Default for Quirks {
+    fn default() -> Quirks {
+        Quirks::COSMAC_VIP
+    }
+}
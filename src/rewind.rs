@@ -0,0 +1,110 @@
+use std::collections::VecDeque;
+
+use crate::cpu::Chip8;
+
+// Ring buffer of machine snapshots for rewinding gameplay. Only the newest
+// snapshot is kept whole; each older one is stored as the XOR against the
+// snapshot after it, run-length encoded, so unchanged memory costs almost nothing.
+pub struct Rewind {
+    current: Option<Vec<u8>>, // newest snapshot
+    deltas: VecDeque<Delta>, // oldest first, each one steps back from the snapshot after it
+    capacity: usize
+}
+
+struct Delta {
+    len: usize, // length of the older snapshot
+    data: Vec<u8> // run-length encoded XOR of the two snapshots
+}
+
+impl Rewind {
+    // Keep up to `capacity` snapshots to step back to
+    pub fn new(capacity: usize) -> Rewind {
+        Rewind {
+            current: None,
+            deltas: VecDeque::with_capacity(capacity),
+            capacity
+        }
+    }
+
+    // Record the machine, normally once per frame
+    pub fn push(&mut self, chip8: &Chip8) {
+        let state = chip8.save_state();
+        if let Some(previous) = self.current.take() {
+            let data = encode(&xor(&previous, &state));
+            self.deltas.push_back(Delta { len: previous.len(), data });
+            if self.deltas.len() > self.capacity {
+                self.deltas.pop_front();
+            }
+        }
+        self.current = Some(state);
+    }
+
+    // Step the machine back to the previous snapshot. Returns false once
+    // there is nothing older left.
+    pub fn rewind(&mut self, chip8: &mut Chip8) -> bool {
+        let (Some(current), Some(delta)) = (self.current.as_mut(), self.deltas.pop_back()) else {
+            return false;
+        };
+
+        let mut previous = xor(current, &decode(&delta.data));
+        previous.truncate(delta.len);
+        // Snapshots come from save_state, so they always load
+        chip8.load_state(&previous).expect("corrupt rewind snapshot");
+        *current = previous;
+        true
+    }
+
+    // Number of snapshots that can be stepped back to
+    pub fn len(&self) -> usize {
+        self.deltas.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.deltas.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.current = None;
+        self.deltas.clear();
+    }
+}
+
+// XOR of two buffers, the shorter one padded with zeros
+fn xor(a: &[u8], b: &[u8]) -> Vec<u8> {
+    (0..a.len().max(b.len()))
+        .map(|i| a.get(i).unwrap_or(&0) ^ b.get(i).unwrap_or(&0))
+        .collect()
+}
+
+// Zero bytes are stored as runs: 0x00 followed by the run length (1-255).
+// Any other byte is stored as is.
+fn encode(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < data.len() {
+        if data[i] == 0 {
+            let run = data[i..].iter().take(255).take_while(|&&b| b == 0).count();
+            out.push(0);
+            out.push(run as u8);
+            i += run;
+        } else {
+            out.push(data[i]);
+            i += 1;
+        }
+    }
+    out
+}
+
+fn decode(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut bytes = data.iter();
+    while let Some(&b) = bytes.next() {
+        if b == 0 {
+            let run = *bytes.next().unwrap_or(&0) as usize;
+            out.resize(out.len() + run, 0);
+        } else {
+            out.push(b);
+        }
+    }
+    out
+}
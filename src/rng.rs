@@ -0,0 +1,69 @@
+// Random numbers for CXNN. The whole state is one u64, so runs can be
+// reproduced from a seed and the generator saved and restored with the machine.
+
+// Which algorithm CXNN uses
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RandomMode {
+    #[default]
+    Xorshift, // xorshift64*, good quality
+    CosmacVip // the COSMAC VIP interpreter's routine, short period and biased like the original
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Rng {
+    state: u64
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        // xorshift never leaves the all-zero state
+        let state = if seed == 0 { 0x9E37_79B9_7F4A_7C15 } else { seed };
+        Rng { state }
+    }
+
+    // Continue from a state(). Xorshift can't be in the all-zero state, the
+    // VIP's counter can.
+    pub fn from_state(state: u64, mode: RandomMode) -> Option<Rng> {
+        (state != 0 || mode == RandomMode::CosmacVip).then_some(Rng { state })
+    }
+
+    pub fn from_entropy() -> Rng {
+        Rng::new(rand::random())
+    }
+
+    pub fn next_u8(&mut self) -> u8 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        (self.state.wrapping_mul(0x2545_F491_4F6C_DD1D) >> 56) as u8
+    }
+
+    /*
+        The VIP interpreter's CXNN, with the low 16 bits of the state as its
+        R9 register. R9 counts up on every 60 Hz interrupt (tick_vip) and once
+        more per CXNN. Its low byte picks a byte of the interpreter's page,
+        which is added to the last result in the high byte; the sum, shifted
+        right through the carry and added to itself, is the new result:
+
+            INC R9; GLO R9; PLO RE; GHI R3; PHI RE   RE = page + R9.0
+            GHI R9; SEX RE; ADD; STR R6              VX = M(RE) + R9.1
+            SHRC; SEX R6; ADD; PHI R9; STR R6        VX = R9.1 = (VX >> 1 | DF << 7) + VX
+            LDA R5; AND; STR R6                      VX &= NN
+    */
+    pub fn next_vip(&mut self, page: &[u8; 256]) -> u8 {
+        let r9 = (self.state as u16).wrapping_add(1);
+        let (sum, carry) = page[r9 as u8 as usize].overflowing_add((r9 >> 8) as u8);
+        let value = (sum >> 1 | (carry as u8) << 7).wrapping_add(sum);
+        self.state = (self.state & !0xFFFF) | (value as u64) << 8 | (r9 & 0xFF) as u64;
+        value
+    }
+
+    // The VIP's interrupt routine counts R9 up every frame
+    pub fn tick_vip(&mut self) {
+        self.state = (self.state & !0xFFFF) | (self.state as u16).wrapping_add(1) as u64;
+    }
+
+    pub fn state(&self) -> u64 {
+        self.state
+    }
+}
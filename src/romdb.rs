@@ -0,0 +1,158 @@
+use std::collections::HashMap;
+
+use crate::json::{self, Json};
+use crate::quirks::{MemoryIncrement, Quirks};
+use crate::sha1::sha1_hex;
+
+// What is known about a ROM: where it came from and how to run it. Read
+// from JSON in the layout of the community chip-8-database's programs.json,
+// a list of programs each with their ROM files keyed by SHA-1:
+//
+//     [{
+//         "title": "Some Game",
+//         "authors": ["Someone"],
+//         "roms": {
+//             "<sha1>": {
+//                 "platforms": ["originalChip8"],
+//                 "quirkyPlatforms": { "originalChip8": { "vblank": false } },
+//                 "tickrate": 15,
+//                 "colors": { "pixels": ["#000000", "#ffffff"] },
+//                 "keys": { "left": 4, "right": 6 }
+//             }
+//         }
+//     }]
+//
+// Other fields are ignored.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RomInfo {
+    pub title: String,
+    pub authors: Vec<String>,
+    pub platform: Option<String>, // the first one listed, like "superchip"
+    pub quirks: Option<Quirks>, // the platform's, with the ROM's quirky overrides
+    pub tickrate: Option<u64>, // instructions per frame
+    pub colors: Vec<(u8, u8, u8)>, // pixel colors: off, plane 1, plane 2, both
+    pub keys: Vec<(String, u8)> // what the hex keys do, like ("left", 4)
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct RomDatabase {
+    roms: HashMap<String, RomInfo> // by lowercase hex SHA-1
+}
+
+// The entries shipped with the emulator
+const BUILTIN: &str = include_str!("../data/programs.json");
+
+impl RomDatabase {
+    pub fn builtin() -> RomDatabase {
+        RomDatabase::parse(BUILTIN).expect("data/programs.json is invalid")
+    }
+
+    pub fn parse(text: &str) -> Result<RomDatabase, String> {
+        let mut roms = HashMap::new();
+
+        for program in json::parse(text)?.as_array() {
+            let title = program.get("title").and_then(Json::as_str).ok_or("program without a title")?;
+            let authors: Vec<String> =
+                program.get("authors").map(Json::as_array).unwrap_or_default().iter().filter_map(Json::as_str).map(String::from).collect();
+
+            for (hash, rom) in program.get("roms").map(Json::as_object).unwrap_or_default() {
+                let platform = rom.get("platforms").and_then(|p| p.as_array().first()).and_then(Json::as_str);
+                let quirks = platform.and_then(|platform| {
+                    let overrides = rom.get("quirkyPlatforms").and_then(|q| q.get(platform));
+                    platform_quirks(platform, overrides)
+                });
+                let colors = rom
+                    .get("colors")
+                    .and_then(|c| c.get("pixels"))
+                    .map(Json::as_array)
+                    .unwrap_or_default()
+                    .iter()
+                    .filter_map(|c| parse_color(c.as_str()?))
+                    .collect();
+                let keys = rom
+                    .get("keys")
+                    .map(Json::as_object)
+                    .unwrap_or_default()
+                    .iter()
+                    .filter_map(|(action, key)| Some((action.clone(), key.as_u64().filter(|&k| k < 16)? as u8)))
+                    .collect();
+
+                let info = RomInfo {
+                    title: title.to_string(),
+                    authors: authors.clone(),
+                    platform: platform.map(String::from),
+                    quirks,
+                    tickrate: rom.get("tickrate").and_then(Json::as_u64).filter(|&t| t > 0),
+                    colors,
+                    keys
+                };
+                roms.insert(hash.to_lowercase(), info);
+            }
+        }
+
+        Ok(RomDatabase { roms })
+    }
+
+    // Add another database's entries, replacing ones for the same ROM
+    pub fn merge(&mut self, other: RomDatabase) {
+        self.roms.extend(other.roms);
+    }
+
+    pub fn lookup(&self, rom: &[u8]) -> Option<&RomInfo> {
+        self.roms.get(&sha1_hex(rom))
+    }
+
+    pub fn len(&self) -> usize {
+        self.roms.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.roms.is_empty()
+    }
+}
+
+// Quirks for a chip-8-database platform id, then its quirk flags (true
+// means the quirky behaviour) applied over them. None for platforms this
+// emulator can't run, like MegaChip.
+fn platform_quirks(platform: &str, overrides: Option<&Json>) -> Option<Quirks> {
+    let mut quirks = match platform {
+        "originalChip8" | "hybridVIP" => Quirks::COSMAC_VIP,
+        // The VIP's instructions without its timing: no vF reset or display wait
+        "modernChip8" => Quirks {
+            shift_uses_vy: true,
+            jump_uses_vx: false,
+            memory_increment: MemoryIncrement::XPlusOne,
+            vf_reset: false,
+            clip_sprites: true,
+            display_wait: false,
+            extended_memory: false
+        },
+        "chip48" => Quirks::CHIP_48,
+        "superchip1" => Quirks::SUPER_CHIP_1_0,
+        "superchip" => Quirks::SUPER_CHIP_1_1,
+        "xochip" => Quirks::XO_CHIP,
+        _ => return None
+    };
+
+    for (name, value) in overrides.map(Json::as_object).unwrap_or_default() {
+        let Some(on) = value.as_bool() else { continue };
+        match name.as_str() {
+            "shift" => quirks.shift_uses_vy = !on,
+            "memoryIncrementByX" if on => quirks.memory_increment = MemoryIncrement::X,
+            "memoryLeaveIUnchanged" if on => quirks.memory_increment = MemoryIncrement::None,
+            "wrap" => quirks.clip_sprites = !on,
+            "jump" => quirks.jump_uses_vx = on,
+            "vblank" => quirks.display_wait = on,
+            "logic" => quirks.vf_reset = on,
+            _ => {}
+        }
+    }
+    Some(quirks)
+}
+
+// "#rrggbb"
+fn parse_color(text: &str) -> Option<(u8, u8, u8)> {
+    let hex = text.strip_prefix('#')?;
+    let rgb = u32::from_str_radix(hex, 16).ok().filter(|_| hex.len() == 6)?;
+    Some(((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8))
+}
@@ -3,7 +3,10 @@ use sdl2::audio::AudioCallback;
 pub struct SquareWave {
     pub phase: f32,
     pub phase_increment: f32,
-    pub volume: f32
+    pub volume: f32,
+    pub pattern: Option<[u8; 16]>, // XO-CHIP audio pattern, played instead of the square wave
+    pub pattern_position: f32, // current bit in the pattern (0 - 128)
+    pub pattern_increment: f32 // pattern bits per output sample
 }
 
 impl AudioCallback for SquareWave {
@@ -11,10 +14,19 @@ impl AudioCallback for SquareWave {
 
     fn callback(&mut self, out: &mut [Self::Channel]) {
         for x in out.iter_mut() {
-            // Square wave: phase is half max amplitude, half min amplitude
-            *x = if self.phase <= 0.5 { self.volume } else { -self.volume };
+            if let Some(pattern) = &self.pattern {
+                // Pattern: play the bits MSB first, 1 is max amplitude, 0 is min amplitude
+                let bit = self.pattern_position as usize;
+                let on = (pattern[bit / 8] >> (7 - bit % 8)) & 1 == 1;
+                *x = if on { self.volume } else { -self.volume };
 
-            self.phase = (self.phase + self.phase_increment) % 1.0;
+                self.pattern_position = (self.pattern_position + self.pattern_increment) % 128.0;
+            } else {
+                // Square wave: phase is half max amplitude, half min amplitude
+                *x = if self.phase <= 0.5 { self.volume } else { -self.volume };
+
+                self.phase = (self.phase + self.phase_increment) % 1.0;
+            }
         }
     }
-}
\ No newline at end of file
+}
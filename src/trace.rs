@@ -0,0 +1,120 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::ops::RangeInclusive;
+
+use crate::instruction::Instruction;
+
+// How much the machine reports, each level includes the ones before it
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum TraceLevel {
+    #[default]
+    Off,
+    Events, // key presses and releases
+    Instructions // one line per instruction executed
+}
+
+impl TraceLevel {
+    pub fn from_name(name: &str) -> Option<TraceLevel> {
+        match name {
+            "off" => Some(TraceLevel::Off),
+            "events" => Some(TraceLevel::Events),
+            "instructions" | "all" => Some(TraceLevel::Instructions),
+            _ => None
+        }
+    }
+}
+
+// Writes a trace of the running machine, attached with Chip8::set_tracer.
+// Instruction lines hold the state before the instruction ran, in a fixed
+// layout so traces from two runs (or another emulator) can be diffed:
+//
+//     0200 00E0 CLS                  V 00000000000000000000000000000000 I 0000
+//
+// pc, opcode, disassembly, V0 - VF and I, all hex.
+pub struct Tracer {
+    level: TraceLevel,
+    out: Box<dyn Write>,
+    addresses: Option<RangeInclusive<u16>>, // only instructions at these addresses
+    classes: u16 // only opcodes whose first nibble has its bit set
+}
+
+impl Tracer {
+    pub fn new(level: TraceLevel, out: Box<dyn Write>) -> Tracer {
+        Tracer { level, out, addresses: None, classes: 0xFFFF }
+    }
+
+    // Trace to a file, or to stdout for "-"
+    pub fn to_file(level: TraceLevel, path: &str) -> io::Result<Tracer> {
+        let out: Box<dyn Write> = if path == "-" {
+            Box::new(BufWriter::new(io::stdout()))
+        } else {
+            Box::new(BufWriter::new(File::create(path)?))
+        };
+        Ok(Tracer::new(level, out))
+    }
+
+    pub fn level(&self) -> TraceLevel {
+        self.level
+    }
+
+    pub fn set_addresses(&mut self, addresses: Option<RangeInclusive<u16>>) {
+        self.addresses = addresses;
+    }
+
+    // Bit n set traces opcodes nXXX, so 1 << 0xD is draws only
+    pub fn set_classes(&mut self, classes: u16) {
+        self.classes = classes;
+    }
+
+    pub(crate) fn instruction(&mut self, pc: u16, opcode: u16, instruction: &Instruction, v: &[u8; 16], i: u16) {
+        if self.level < TraceLevel::Instructions
+            || self.classes & (1 << (opcode >> 12)) == 0
+            || self.addresses.as_ref().is_some_and(|range| !range.contains(&pc))
+        {
+            return;
+        }
+
+        let mut registers = String::with_capacity(32);
+        for value in v {
+            registers += &format!("{:02X}", value);
+        }
+        // Trace output is best effort, a full disk should not stop the machine
+        let _ = writeln!(self.out, "{:04X} {:04X} {:<22} V {} I {:04X}", pc, opcode, instruction.to_string(), registers, i);
+    }
+
+    pub(crate) fn event(&mut self, message: fmt::Arguments) {
+        if self.level >= TraceLevel::Events {
+            let _ = writeln!(self.out, "{}", message);
+        }
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+// Address range for set_addresses, like "200-2FF" or a single "2A4"
+pub fn parse_address_range(text: &str) -> Result<RangeInclusive<u16>, String> {
+    let (start, end) = text.split_once('-').unwrap_or((text, text));
+    let address = |s: &str| {
+        let s = s.trim();
+        u16::from_str_radix(s.strip_prefix("0x").unwrap_or(s), 16).map_err(|_| format!("invalid address '{}'", s))
+    };
+    let (start, end) = (address(start)?, address(end)?);
+    if start > end {
+        return Err(format!("empty address range '{}'", text));
+    }
+    Ok(start..=end)
+}
+
+// Opcode classes for set_classes, first nibbles like "8,D,F"
+pub fn parse_opcode_classes(text: &str) -> Result<u16, String> {
+    let mut classes = 0;
+    for class in text.split(',') {
+        let class = class.trim();
+        let nibble = u8::from_str_radix(class, 16).ok().filter(|&n| n < 16 && class.len() == 1);
+        classes |= 1 << nibble.ok_or(format!("invalid opcode class '{}', expected a hex digit", class))?;
+    }
+    Ok(classes)
+}
@@ -0,0 +1,74 @@
+// Octo assembler: labels, directives, control flow and range errors
+
+use chip8::assembler::assemble;
+
+fn error(source: &str) -> String {
+    assemble(source).unwrap_err().to_string()
+}
+
+#[test]
+fn labels_resolve_forward_and_backward() {
+    let rom = assemble(": main sub jump main : sub v0 := 1 return").unwrap();
+    assert_eq!(rom, [0x12, 0x02, 0x22, 0x06, 0x12, 0x02, 0x60, 0x01, 0x00, 0xEE]);
+    assert_eq!(error(": main jump nowhere"), "line 1: undefined name 'nowhere'");
+}
+
+#[test]
+fn org_const_and_byte() {
+    let source = "
+:const SPEED 3
+: main
+  v1 += SPEED
+  i := data
+:org 0x300
+: data
+  :byte 0xAB
+  0x01 0x02
+";
+    let rom = assemble(source).unwrap();
+    assert_eq!(&rom[..6], [0x12, 0x02, 0x71, 0x03, 0xA3, 0x00]);
+    assert_eq!(rom.len(), 0x103);
+    assert_eq!(&rom[0x100..], [0xAB, 0x01, 0x02]);
+    assert_eq!(error(": main :byte 256"), "line 1: value 256 out of range -128 to 255");
+}
+
+#[test]
+fn if_else_end() {
+    let source = ": main if v0 == 1 begin v1 := 2 else v1 := 3 end";
+    assert_eq!(
+        assemble(source).unwrap(),
+        [
+            0x12, 0x02, // jump main
+            0x30, 0x01, // skip the jump to else when v0 == 1
+            0x12, 0x0A, // to else
+            0x61, 0x02,
+            0x12, 0x0C, // past end
+            0x61, 0x03
+        ]
+    );
+    assert_eq!(assemble(": main if v0 != 4 then v2 += 1").unwrap(), [0x12, 0x02, 0x30, 0x04, 0x72, 0x01]);
+}
+
+#[test]
+fn loop_while_again() {
+    let source = ": main loop v0 += 1 while v0 != 10 again";
+    assert_eq!(
+        assemble(source).unwrap(),
+        [
+            0x12, 0x02,
+            0x70, 0x01, // loop start
+            0x40, 0x0A, // skip the break while v0 != 10
+            0x12, 0x0A, // break, past again
+            0x12, 0x02 // again
+        ]
+    );
+}
+
+#[test]
+fn jumps_past_0xfff_are_errors() {
+    assert_eq!(error(": main :org 0x1100 : top loop v0 += 1 again"), "line 1: jump target 0x1100 out of range");
+    assert_eq!(error(": main if v0 == 1 begin :org 0x1100 v1 := 2 end"), "line 1: jump target 0x1102 out of range");
+    assert_eq!(error(": main if v0 == 1 begin :org 0x1100 else end"), "line 1: jump target 0x1102 out of range");
+    assert_eq!(error(": main jump 0x1000"), "line 1: address 0x1000 out of range");
+    assert_eq!(error(": main jump far :org 0x1000 : far"), "line 1: address of 'far' does not fit in 12 bits");
+}
@@ -0,0 +1,48 @@
+// Config file parsing and the ROM hashes its sections are keyed by
+
+use chip8::config::{rom_hash, Config};
+
+#[test]
+fn rom_hash_is_sha1() {
+    assert_eq!(rom_hash(b""), "da39a3ee5e6b4b0d3255bfef95601890afd80709");
+    assert_eq!(rom_hash(b"abc"), "a9993e364706816aba3e25717850c26c9cd0d89d");
+    // Two blocks of padding
+    assert_eq!(
+        rom_hash(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"),
+        "84983e441c3bd26ebaae4aa1f95129e5e54670f1"
+    );
+    assert_eq!(rom_hash(&vec![b'a'; 1_000_000]), "34aa973cd4c4daa4f61eeb2bdbad27316534016f");
+}
+
+#[test]
+fn rom_section_comes_after_global() {
+    let rom = b"abc";
+    let text = "\
+scale = 10
+
+# Tetris
+[rom A9993E364706816ABA3E25717850C26C9CD0D89D]
+quirks = vip
+key.4 = Left, A
+
+[global]
+mute = true
+
+[rom da39a3ee5e6b4b0d3255bfef95601890afd80709]
+quirks = xochip
+";
+    let config = Config::parse(text).unwrap();
+    let entries: Vec<(&str, &str, usize)> =
+        config.entries_for(rom).iter().map(|e| (e.key.as_str(), e.value.as_str(), e.line)).collect();
+    assert_eq!(entries, [("scale", "10", 1), ("mute", "true", 9), ("quirks", "vip", 5), ("key.4", "Left, A", 6)]);
+
+    let other: Vec<&str> = config.entries_for(b"other").iter().map(|e| e.key.as_str()).collect();
+    assert_eq!(other, ["scale", "mute"]);
+}
+
+#[test]
+fn parse_errors_name_the_line() {
+    assert_eq!(Config::parse("[global]\nscale 10").unwrap_err(), "line 2: expected 'key = value'");
+    assert!(Config::parse("[rom 1234]").unwrap_err().starts_with("line 1: unknown section [rom 1234]"));
+    assert_eq!(Config::parse("; comment\n[global").unwrap_err(), "line 2: expected ']'");
+}
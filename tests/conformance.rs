@@ -0,0 +1,232 @@
+// Conformance tests: run test ROMs headlessly under each quirks preset and
+// compare a hash of the final display against golden files.
+//
+// ROMs come from tests/roms: Octo sources (.8o) are assembled on the fly,
+// binaries (.ch8) are read as they are. The IBM logo demo is checked in;
+// Timendus' test suite has to be added by hand, see tests/roms/README.md.
+// Its tests are ignored, run them with `cargo test --test conformance -- --ignored`.
+//
+// Golden files live in tests/golden/<rom>.<preset>.txt: the hash, then the
+// display as text so a failure can show which pixels changed. To create or
+// update them after an intended change, run
+//
+//     CHIP8_BLESS=1 cargo test --test conformance
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chip8::assembler::assemble;
+use chip8::headless::{display_ascii, run, KeyEvent, Limit};
+use chip8::{Chip8, Quirks};
+
+const CYCLES_PER_FRAME: u64 = 12;
+
+struct Case {
+    rom: &'static str, // file in tests/roms
+    presets: &'static [&'static str], // Quirks::from_name names
+    frames: u64,
+    keys: &'static [KeyEvent]
+}
+
+const fn press(frame: u64, key: u8) -> [KeyEvent; 2] {
+    [KeyEvent { frame, key, pressed: true }, KeyEvent { frame: frame + 5, key, pressed: false }]
+}
+
+// Timendus' menus pick a test with a key press
+const QUIRKS_CHIP8: [KeyEvent; 2] = press(10, 0x1);
+const QUIRKS_SCHIP: [KeyEvent; 2] = press(10, 0x2);
+const QUIRKS_XOCHIP: [KeyEvent; 2] = press(10, 0x3);
+const KEYPAD_FX0A: [KeyEvent; 4] = {
+    let [a, b] = press(10, 0x3);
+    let [c, d] = press(30, 0x5);
+    [a, b, c, d]
+};
+
+fn check(case: Case) {
+    let path = Path::new("tests/roms").join(case.rom);
+    let rom = load_rom(&path).unwrap_or_else(|| panic!("{} not found, see tests/roms/README.md", path.display()));
+
+    let bless = env::var_os("CHIP8_BLESS").is_some();
+    let mut failures = Vec::new();
+
+    for preset in case.presets {
+        let quirks = Quirks::from_name(preset).expect("unknown preset");
+        let mut chip8 = Chip8::new(quirks);
+        chip8.init();
+        chip8.set_seed(0);
+        chip8.load_bin(&rom).expect("ROM does not fit in memory");
+
+        let result = run(&mut chip8, Limit::Frames(case.frames), CYCLES_PER_FRAME, case.keys);
+        if let Some(e) = result.error {
+            failures.push(format!("{} ({}): {}", case.rom, preset, e));
+            continue;
+        }
+
+        let (width, height) = chip8.resolution();
+        let actual = Golden { hash: display_hash(&chip8), width, height, screen: display_ascii(&chip8) };
+        let golden_path = golden_path(case.rom, preset);
+
+        if bless {
+            fs::write(&golden_path, actual.to_string()).expect("could not write golden file");
+            continue;
+        }
+
+        match fs::read_to_string(&golden_path).ok().and_then(|text| Golden::parse(&text)) {
+            Some(expected) if expected.hash == actual.hash => {}
+            Some(expected) => failures.push(format!(
+                "{} ({}): display hash {:016x}, expected {:016x}\n{}",
+                case.rom,
+                preset,
+                actual.hash,
+                expected.hash,
+                diff(&expected, &actual)
+            )),
+            None => failures.push(format!(
+                "{} ({}): no golden file at {}, run with CHIP8_BLESS=1 to create it",
+                case.rom,
+                preset,
+                golden_path.display()
+            ))
+        }
+    }
+
+    assert!(failures.is_empty(), "\n{}", failures.join("\n\n"));
+}
+
+// Assemble Octo sources, read anything else as a binary ROM
+fn load_rom(path: &Path) -> Option<Vec<u8>> {
+    if path.extension().is_some_and(|ext| ext == "8o") {
+        let source = fs::read_to_string(path).ok()?;
+        match assemble(&source) {
+            Ok(rom) => Some(rom),
+            Err(e) => panic!("{}:{}: {}", path.display(), e.line, e.message)
+        }
+    } else {
+        fs::read(path).ok()
+    }
+}
+
+fn golden_path(rom: &str, preset: &str) -> PathBuf {
+    let name = Path::new(rom).file_stem().unwrap().to_string_lossy();
+    Path::new("tests/golden").join(format!("{}.{}.txt", name, preset))
+}
+
+// FNV-1a over the resolution and the pixels
+fn display_hash(chip8: &Chip8) -> u64 {
+    let (width, height) = chip8.resolution();
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for &byte in [width as u8, height as u8].iter().chain(chip8.display()) {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash
+}
+
+struct Golden {
+    hash: u64,
+    width: usize,
+    height: usize,
+    screen: String
+}
+
+impl Golden {
+    fn parse(text: &str) -> Option<Golden> {
+        let mut lines = text.lines();
+        let hash = u64::from_str_radix(lines.next()?.strip_prefix("hash ")?, 16).ok()?;
+        let (width, height) = lines.next()?.split_once('x')?;
+        let screen: Vec<&str> = lines.collect();
+        Some(Golden {
+            hash,
+            width: width.parse().ok()?,
+            height: height.parse().ok()?,
+            screen: screen.join("\n") + "\n"
+        })
+    }
+}
+
+impl std::fmt::Display for Golden {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "hash {:016x}\n{}x{}\n{}", self.hash, self.width, self.height, self.screen)
+    }
+}
+
+// Rows that differ, expected over actual, with the changed columns marked
+fn diff(expected: &Golden, actual: &Golden) -> String {
+    if (expected.width, expected.height) != (actual.width, actual.height) {
+        return format!(
+            "resolution {}x{}, expected {}x{}\nactual display:\n{}",
+            actual.width, actual.height, expected.width, expected.height, actual.screen
+        );
+    }
+
+    let mut out = String::new();
+    for (row, (want, got)) in expected.screen.lines().zip(actual.screen.lines()).enumerate() {
+        if want == got {
+            continue;
+        }
+        let marks: String = want.chars().zip(got.chars()).map(|(a, b)| if a == b { ' ' } else { '^' }).collect();
+        out += &format!("row {:2} expected {}\n       actual   {}\n                {}\n", row, want, got, marks);
+    }
+    out
+}
+
+#[test]
+fn alu() {
+    check(Case { rom: "alu.8o", presets: &["vip", "schip1.1", "xochip"], frames: 120, keys: &[] });
+}
+
+#[test]
+fn schip_display() {
+    check(Case { rom: "schip.8o", presets: &["schip1.1", "xochip"], frames: 30, keys: &[] });
+}
+
+#[test]
+fn xochip_planes() {
+    check(Case { rom: "xochip.8o", presets: &["xochip"], frames: 30, keys: &[] });
+}
+
+#[test]
+#[ignore = "needs external ROM"]
+fn chip8_logo() {
+    check(Case { rom: "1-chip8-logo.ch8", presets: &["vip"], frames: 60, keys: &[] });
+}
+
+// The classic IBM logo demo, checked in. Its golden screen is the logo at
+// (12, 8), which can be checked by eye rather than trusting this emulator.
+#[test]
+fn ibm_logo() {
+    check(Case { rom: "ibm-logo.ch8", presets: &["vip", "schip1.1", "xochip"], frames: 60, keys: &[] });
+}
+
+#[test]
+#[ignore = "needs external ROM"]
+fn timendus_ibm_logo() {
+    check(Case { rom: "2-ibm-logo.ch8", presets: &["vip"], frames: 60, keys: &[] });
+}
+
+#[test]
+#[ignore = "needs external ROM"]
+fn corax_opcodes() {
+    check(Case { rom: "3-corax+.ch8", presets: &["vip", "schip1.1", "xochip"], frames: 120, keys: &[] });
+}
+
+#[test]
+#[ignore = "needs external ROM"]
+fn flags() {
+    check(Case { rom: "4-flags.ch8", presets: &["vip", "schip1.1", "xochip"], frames: 120, keys: &[] });
+}
+
+#[test]
+#[ignore = "needs external ROM"]
+fn quirks() {
+    check(Case { rom: "5-quirks.ch8", presets: &["vip"], frames: 300, keys: &QUIRKS_CHIP8 });
+    check(Case { rom: "5-quirks.ch8", presets: &["schip1.1"], frames: 300, keys: &QUIRKS_SCHIP });
+    check(Case { rom: "5-quirks.ch8", presets: &["xochip"], frames: 300, keys: &QUIRKS_XOCHIP });
+}
+
+#[test]
+#[ignore = "needs external ROM"]
+fn keypad() {
+    check(Case { rom: "6-keypad.ch8", presets: &["vip"], frames: 120, keys: &KEYPAD_FX0A });
+}
@@ -0,0 +1,217 @@
+// Breakpoints, watchpoints and stepping, driven the way the frontend does
+
+use chip8::assembler::assemble;
+use chip8::debugger::{parse_command, Debugger};
+use chip8::Chip8;
+
+fn machine(source: &str) -> Chip8 {
+    let mut chip8 = Chip8::new(Default::default());
+    chip8.init();
+    chip8.load_bin(&assemble(source).unwrap()).unwrap();
+    chip8.set_access_logging(true);
+    chip8
+}
+
+fn command(debugger: &mut Debugger, chip8: &mut Chip8, line: &str) -> String {
+    debugger.command(parse_command(line).unwrap(), chip8)
+}
+
+// Run frames until the debugger stops execution
+fn run(debugger: &mut Debugger, chip8: &mut Chip8) {
+    for _ in 0..100 {
+        if chip8.run_frame_until(10, |chip8| debugger.should_break(chip8)).stopped {
+            return;
+        }
+    }
+    panic!("the debugger never stopped");
+}
+
+const COUNTER: &str = "
+: main
+  v0 := 0
+: top
+  v0 += 1
+  i := 0x300
+  save v0
+  jump top
+";
+
+#[test]
+fn breakpoint_stops_before_the_instruction() {
+    let mut chip8 = machine(COUNTER);
+    let mut debugger = Debugger::new();
+    command(&mut debugger, &mut chip8, "b 0x206");
+    command(&mut debugger, &mut chip8, "c");
+
+    run(&mut debugger, &mut chip8);
+    assert_eq!(chip8.pc(), 0x206);
+    assert_eq!(chip8.v()[0], 1);
+    assert_eq!(debugger.take_reason().as_deref(), Some("Breakpoint at 0x206"));
+
+    // Continuing runs the loop once more to the same breakpoint
+    command(&mut debugger, &mut chip8, "continue");
+    run(&mut debugger, &mut chip8);
+    assert_eq!(chip8.pc(), 0x206);
+    assert_eq!(chip8.v()[0], 2);
+}
+
+#[test]
+fn memory_watchpoint_stops_after_a_write() {
+    let mut chip8 = machine(COUNTER);
+    let mut debugger = Debugger::new();
+    assert_eq!(command(&mut debugger, &mut chip8, "w 0x300"), "Watchpoint 0: write 0x300");
+    command(&mut debugger, &mut chip8, "c");
+
+    run(&mut debugger, &mut chip8);
+    // Stopped right after save v0
+    assert_eq!(chip8.pc(), 0x20A);
+    assert_eq!(chip8.memory()[0x300], 1);
+    assert_eq!(debugger.take_reason().as_deref(), Some("Watchpoint 0: write 0x300"));
+
+    // Reads don't trigger a write watchpoint
+    let mut chip8 = machine(": main i := 0x300 load v0 jump main");
+    let mut debugger = Debugger::new();
+    command(&mut debugger, &mut chip8, "w 0x300 w");
+    command(&mut debugger, &mut chip8, "b 0x206");
+    command(&mut debugger, &mut chip8, "c");
+    run(&mut debugger, &mut chip8);
+    assert_eq!(debugger.take_reason().as_deref(), Some("Breakpoint at 0x206"));
+}
+
+#[test]
+fn step_runs_one_instruction() {
+    let mut chip8 = machine(COUNTER);
+    let mut debugger = Debugger::new();
+    // Starts paused
+    assert!(chip8.run_frame_until(10, |chip8| debugger.should_break(chip8)).stopped);
+    assert_eq!(chip8.pc(), 0x200);
+
+    for pc in [0x202, 0x204, 0x206] {
+        command(&mut debugger, &mut chip8, "s");
+        let result = chip8.run_frame_until(10, |chip8| debugger.should_break(chip8));
+        assert_eq!(result.cycles, 1);
+        assert_eq!(chip8.pc(), pc);
+        assert!(debugger.is_paused());
+    }
+    assert_eq!(chip8.v()[0], 1);
+}
+
+const CALLS: &str = "
+: main
+  sub
+  v1 := 7
+  jump main
+: sub
+  v0 += 1
+  v0 += 1
+  return
+";
+
+// Paused at main, after the jump to it at 0x200
+fn paused(source: &str) -> (Debugger, Chip8) {
+    let mut chip8 = machine(source);
+    let mut debugger = Debugger::new();
+    assert!(chip8.run_frame_until(10, |chip8| debugger.should_break(chip8)).stopped);
+    command(&mut debugger, &mut chip8, "s");
+    run(&mut debugger, &mut chip8);
+    assert_eq!(chip8.pc(), 0x202);
+    (debugger, chip8)
+}
+
+#[test]
+fn next_steps_over_a_call() {
+    let (mut debugger, mut chip8) = paused(CALLS);
+    command(&mut debugger, &mut chip8, "n");
+    run(&mut debugger, &mut chip8);
+    assert_eq!(chip8.pc(), 0x204);
+    assert_eq!(chip8.v()[0], 2);
+    assert!(chip8.stack().is_empty());
+
+    // Anything else is a single step
+    command(&mut debugger, &mut chip8, "next");
+    run(&mut debugger, &mut chip8);
+    assert_eq!(chip8.pc(), 0x206);
+    assert_eq!(chip8.v()[1], 7);
+}
+
+#[test]
+fn finish_runs_to_the_return() {
+    let (mut debugger, mut chip8) = paused(CALLS);
+    assert_eq!(command(&mut debugger, &mut chip8, "f"), "Not in a subroutine");
+
+    command(&mut debugger, &mut chip8, "s");
+    run(&mut debugger, &mut chip8);
+    assert_eq!(chip8.pc(), 0x208);
+    assert_eq!(chip8.stack(), [0x204]);
+
+    command(&mut debugger, &mut chip8, "finish");
+    run(&mut debugger, &mut chip8);
+    assert_eq!(chip8.pc(), 0x204);
+    assert_eq!(chip8.v()[0], 2);
+    assert!(chip8.stack().is_empty());
+}
+
+#[test]
+fn until_runs_to_an_address() {
+    let (mut debugger, mut chip8) = paused(CALLS);
+    command(&mut debugger, &mut chip8, "u 0x20C");
+    run(&mut debugger, &mut chip8);
+    assert_eq!(chip8.pc(), 0x20C);
+    assert_eq!(chip8.v()[0], 2);
+    assert_eq!(debugger.take_reason(), None);
+}
+
+#[test]
+fn register_watchpoint_stops_on_a_matching_change() {
+    let (mut debugger, mut chip8) = paused(CALLS);
+    assert_eq!(command(&mut debugger, &mut chip8, "w v1 == 7"), "Watchpoint 0: V1 becomes == 0x7");
+    command(&mut debugger, &mut chip8, "c");
+    run(&mut debugger, &mut chip8);
+    // Stopped right after v1 := 7
+    assert_eq!(chip8.pc(), 0x206);
+    assert_eq!(debugger.take_reason().as_deref(), Some("Watchpoint 0: V1 becomes == 0x7"));
+}
+
+#[test]
+fn i_watchpoint_stops_when_i_changes() {
+    let (mut debugger, mut chip8) = paused(": main i := 0x300 v0 += 1 jump main");
+    command(&mut debugger, &mut chip8, "w i");
+    command(&mut debugger, &mut chip8, "c");
+    run(&mut debugger, &mut chip8);
+    assert_eq!(chip8.pc(), 0x204);
+    assert_eq!(chip8.i(), 0x300);
+    assert_eq!(debugger.take_reason().as_deref(), Some("Watchpoint 0: I changes"));
+
+    // Setting it to the same value again is no change
+    command(&mut debugger, &mut chip8, "b 0x204");
+    command(&mut debugger, &mut chip8, "c");
+    run(&mut debugger, &mut chip8);
+    assert_eq!(debugger.take_reason().as_deref(), Some("Breakpoint at 0x204"));
+    assert_eq!(chip8.v()[0], 1);
+}
+
+#[test]
+fn timer_watchpoint_stops_as_the_timer_counts_down() {
+    let (mut debugger, mut chip8) = paused(": main v0 := 5 delay := v0 : wait jump wait");
+    command(&mut debugger, &mut chip8, "w dt < 3");
+    command(&mut debugger, &mut chip8, "c");
+    run(&mut debugger, &mut chip8);
+    assert_eq!(chip8.delay_timer(), 2);
+    assert_eq!(debugger.take_reason().as_deref(), Some("Watchpoint 0: DT becomes < 0x3"));
+}
+
+#[test]
+fn stack_depth_watchpoint_stops_on_calls() {
+    let (mut debugger, mut chip8) = paused(CALLS);
+    command(&mut debugger, &mut chip8, "w sp > 0");
+    command(&mut debugger, &mut chip8, "c");
+    run(&mut debugger, &mut chip8);
+    // Stopped in the subroutine, not on the way back out
+    assert_eq!(chip8.pc(), 0x208);
+    assert_eq!(debugger.take_reason().as_deref(), Some("Watchpoint 0: stack depth becomes > 0x0"));
+    // Returning makes it 0, which doesn't match, so the next stop is the next call
+    command(&mut debugger, &mut chip8, "c");
+    run(&mut debugger, &mut chip8);
+    assert_eq!(chip8.pc(), 0x208);
+    assert_eq!(chip8.v()[0], 2);
+}
@@ -0,0 +1,50 @@
+// Disassembler output for a small program with code and data
+
+use chip8::assembler::assemble;
+use chip8::disasm::disassemble;
+
+#[test]
+fn code_and_data_listing() {
+    let source = "
+: main
+  i := face
+  v0 := 10
+  v1 := 8
+  draw
+  sprite v0 v1 5
+  i := mouth
+: halt
+  jump halt
+: draw
+  v2 += 1
+  return
+: face
+  0x3C 0x42 0xA5 0x81 0xFF 0x81
+: mouth
+  0xBD 0x42
+";
+    // Starts with a newline to keep the first line's indent
+    let expected = "
+  0x200  12 02       JP L202
+L202:
+  0x202  A2 14       LD I, data_214
+  0x204  60 0A       LD V0, 0x0A
+  0x206  61 08       LD V1, 0x08
+  0x208  22 10       CALL sub_210
+  0x20A  D0 15       DRW V0, V1, 5
+  0x20C  A2 1A       LD I, data_21A
+L20E:
+  0x20E  12 0E       JP L20E
+sub_210:
+  0x210  72 01       ADD V2, 0x01
+  0x212  00 EE       RET
+data_214:
+  0x214  3C 42 A5 81 :byte 0x3C 0x42 0xA5 0x81  ; ..####.. .#....#. #.#..#.# #......#
+  0x218  FF 81       :byte 0xFF 0x81  ; ######## #......#
+data_21A:
+  0x21A  BD 42       :byte 0xBD 0x42  ; #.####.# .#....#.
+";
+    let rom = assemble(source).unwrap();
+    let listing: String = disassemble(&rom).iter().map(|line| format!("{line}\n")).collect();
+    assert_eq!(listing, &expected[1..]);
+}
@@ -0,0 +1,117 @@
+// Controller input mapping, driven by made-up events instead of a controller
+
+use chip8::config::Config;
+use chip8::gamepad::{Axis, Button, Gamepads, PadInput, PadMap, AXIS_THRESHOLD};
+use chip8::romdb::RomInfo;
+
+fn press(button: Button) -> PadInput {
+    PadInput::Button(button, true)
+}
+
+fn release(button: Button) -> PadInput {
+    PadInput::Button(button, false)
+}
+
+#[test]
+fn buttons_press_their_hex_keys() {
+    let mut pads = Gamepads::new(PadMap::new());
+    assert_eq!(pads.input(0, press(Button::DPadLeft)), [(0x7, true)]);
+    assert_eq!(pads.input(0, press(Button::A)), [(0x6, true)]);
+    assert_eq!(pads.keys(), 1 << 0x7 | 1 << 0x6);
+    assert_eq!(pads.input(0, release(Button::DPadLeft)), [(0x7, false)]);
+    // Unbound buttons do nothing
+    assert_eq!(pads.input(0, press(Button::Start)), []);
+    assert_eq!(pads.keys(), 1 << 0x6);
+}
+
+#[test]
+fn key_stays_down_while_any_of_its_buttons_is_held() {
+    let mut map = PadMap::new();
+    map.bind(0x4, "dpleft, x").unwrap();
+    let mut pads = Gamepads::new(map);
+
+    assert_eq!(pads.input(0, press(Button::DPadLeft)), [(0x4, true)]);
+    assert_eq!(pads.input(0, press(Button::X)), []);
+    assert_eq!(pads.input(0, release(Button::DPadLeft)), []);
+    assert_eq!(pads.input(0, release(Button::X)), [(0x4, false)]);
+
+    // Two controllers holding the same key
+    pads.input(0, press(Button::X));
+    pads.input(1, press(Button::X));
+    assert_eq!(pads.input(0, release(Button::X)), []);
+    assert_eq!(pads.disconnect(1), [(0x4, false)]);
+}
+
+#[test]
+fn stick_and_triggers_act_as_buttons() {
+    let mut pads = Gamepads::new(PadMap::new());
+    // Inside the dead zone
+    assert_eq!(pads.input(0, PadInput::Axis(Axis::LeftX, AXIS_THRESHOLD - 1)), []);
+    assert_eq!(pads.input(0, PadInput::Axis(Axis::LeftX, i16::MIN)), [(0x7, true)]);
+    // Swinging across releases one side and presses the other
+    assert_eq!(pads.input(0, PadInput::Axis(Axis::LeftX, i16::MAX)), [(0x7, false), (0x9, true)]);
+    assert_eq!(pads.input(0, PadInput::Axis(Axis::LeftY, -20_000)), [(0x5, true)]);
+    // The stick and the D-pad share a key without releasing each other
+    pads.input(0, press(Button::DPadUp));
+    assert_eq!(pads.input(0, PadInput::Axis(Axis::LeftY, 0)), []);
+    assert_eq!(pads.input(0, release(Button::DPadUp)), [(0x5, false)]);
+
+    let mut map = PadMap::new();
+    map.add(Button::RightTrigger, 0xF);
+    let mut pads = Gamepads::new(map);
+    assert_eq!(pads.input(0, PadInput::Axis(Axis::TriggerRight, 32_000)), [(0xF, true)]);
+    assert_eq!(pads.input(0, PadInput::Axis(Axis::TriggerRight, 100)), [(0xF, false)]);
+}
+
+#[test]
+fn rom_profiles_come_from_config_sections_and_database_actions() {
+    let tetris = b"tetris";
+    let text = format!(
+        "\
+[global]
+pad.5 = start
+
+[rom {}]
+pad.4 = dpleft
+pad.6 = dpright
+pad.5 = a, dpup
+",
+        chip8::config::rom_hash(tetris)
+    );
+    let config = Config::parse(&text).unwrap();
+    let profile = |rom: &[u8], info: Option<&RomInfo>| PadMap::from_config(&config, rom, info).unwrap();
+
+    let map = profile(tetris, None);
+    assert_eq!(map.hex_key(Button::DPadLeft), Some(0x4));
+    assert_eq!(map.hex_key(Button::DPadRight), Some(0x6));
+    assert_eq!(map.buttons_for(0x5).collect::<Vec<_>>(), [Button::A, Button::DPadUp]);
+    // The ROM's section replaced the global binding
+    assert_eq!(map.hex_key(Button::Start), None);
+
+    let map = profile(b"another", None);
+    assert_eq!(map.hex_key(Button::DPadLeft), Some(0x7));
+    assert_eq!(map.buttons_for(0x5).collect::<Vec<_>>(), [Button::Start]);
+
+    // Database actions go over [global] but under the ROM's section
+    let info = RomInfo {
+        title: "Tetris".to_string(),
+        authors: Vec::new(),
+        platform: None,
+        quirks: None,
+        tickrate: None,
+        colors: Vec::new(),
+        keys: vec![("left".to_string(), 0x1), ("b".to_string(), 0x5), ("player2Up".to_string(), 0x2)]
+    };
+    let map = profile(tetris, Some(&info));
+    assert_eq!(map.hex_key(Button::DPadLeft), Some(0x4));
+    assert_eq!(map.buttons_for(0x5).collect::<Vec<_>>(), [Button::A, Button::DPadUp]);
+    let map = profile(b"another", Some(&info));
+    assert_eq!(map.hex_key(Button::DPadLeft), Some(0x1));
+    assert_eq!(map.buttons_for(0x5).collect::<Vec<_>>(), [Button::Start, Button::B]);
+    assert_eq!(map.buttons_for(0x2).collect::<Vec<_>>(), [Button::Y]);
+
+    let bad = Config::parse("[global]\npad.5 = a\npad.g = b\n").unwrap();
+    assert_eq!(PadMap::from_config(&bad, tetris, None), Err("line 3: invalid hex key 'g' in pad.g".to_string()));
+    let bad = Config::parse("pad.1 = dpleft, turbo\n").unwrap();
+    assert_eq!(PadMap::from_config(&bad, tetris, None), Err("line 1: unknown controller button 'turbo'".to_string()));
+}
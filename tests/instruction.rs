@@ -0,0 +1,18 @@
+// Decoding and encoding opcodes
+
+use chip8::{decode, Instruction};
+
+#[test]
+fn encode_inverts_decode_for_every_opcode() {
+    for opcode in 0x0000..=0xFFFF {
+        let instruction = decode(opcode);
+        assert_eq!(instruction.encode(), opcode, "{opcode:04X} decoded to {instruction:?}");
+    }
+}
+
+#[test]
+fn unassigned_opcodes_decode_as_unknown() {
+    for opcode in [0x0000, 0x5001, 0x800F, 0x9001, 0xE000, 0xF0FF] {
+        assert_eq!(decode(opcode), Instruction::Unknown(opcode));
+    }
+}
@@ -0,0 +1,49 @@
+// Movie files and the ROM they were recorded on
+
+use chip8::movie::Movie;
+use chip8::{Quirks, RandomMode};
+
+#[test]
+fn movies_round_trip_through_text() {
+    let mut movie = Movie::new(&[0x12, 0x00], 42, RandomMode::CosmacVip, 12, Quirks::default());
+    movie.record(3, 0x5, true);
+    movie.record(9, 0x5, false);
+
+    let text = movie.to_string();
+    assert_eq!(text.lines().nth(1), Some("rom 92a5652d382a18e89c4881ec57041fc7d885ca80"));
+    assert!(text.contains("\nrandom vip\n"));
+    assert_eq!(Movie::parse(&text), Ok(movie));
+}
+
+#[test]
+fn another_rom_is_refused() {
+    let movie = Movie::new(&[0x12, 0x00], 42, RandomMode::Xorshift, 12, Quirks::default());
+    assert!(movie.matches_rom(&[0x12, 0x00]));
+    assert!(movie.machine(&[0x12, 0x00]).is_ok());
+    assert!(!movie.matches_rom(&[0x12, 0x02]));
+    assert!(movie.machine(&[0x12, 0x02]).err().unwrap().contains("another ROM"));
+}
+
+#[test]
+fn bad_rom_lines_are_errors() {
+    let text = Movie::new(&[0x12, 0x00], 42, RandomMode::Xorshift, 12, Quirks::default()).to_string();
+    let rom_line = text.lines().nth(1).unwrap();
+
+    let crc = text.replace(rom_line, "rom 1a2b3c4d");
+    assert_eq!(Movie::parse(&crc), Err("invalid ROM SHA-1 '1a2b3c4d'".to_string()));
+
+    let old = crc.replace("chip8-movie 2", "chip8-movie 1");
+    assert_eq!(Movie::parse(&old), Err("movie from an older version, record it again".to_string()));
+
+    let missing = text.replace(&format!("{rom_line}\n"), "");
+    assert_eq!(Movie::parse(&missing), Err("missing 'rom'".to_string()));
+}
+
+#[test]
+fn unknown_fields_are_errors() {
+    let text = Movie::new(&[0x12, 0x00], 42, RandomMode::Xorshift, 12, Quirks::default()).to_string();
+    let extra = text.replace("seed 42\n", "seed 42\nspeed 3\n");
+    assert_eq!(Movie::parse(&extra), Err("unknown movie field 'speed'".to_string()));
+    let random = text.replace("random xorshift", "random lcg");
+    assert_eq!(Movie::parse(&random), Err("unknown random mode 'lcg'".to_string()));
+}
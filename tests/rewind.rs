@@ -0,0 +1,72 @@
+// Rewinding through per-frame snapshots
+
+use chip8::assembler::assemble;
+use chip8::rewind::Rewind;
+use chip8::Chip8;
+
+// Counts frames in v0 and draws a changing sprite, so every frame differs
+fn machine() -> Chip8 {
+    let source = "
+: main
+  v0 += 1
+  i := 0x300
+  save v0
+  sprite v0 v0 1
+  jump main
+";
+    let mut chip8 = Chip8::new(Default::default());
+    chip8.init();
+    chip8.load_bin(&assemble(source).unwrap()).unwrap();
+    chip8
+}
+
+#[test]
+fn rewind_restores_earlier_frames_exactly() {
+    let mut chip8 = machine();
+    let mut rewind = Rewind::new(10);
+    let mut states = vec![chip8.save_state()];
+    rewind.push(&chip8);
+    for _ in 0..6 {
+        chip8.run_frame(5);
+        states.push(chip8.save_state());
+        rewind.push(&chip8);
+    }
+    assert_eq!(rewind.len(), 6);
+
+    // Back one frame at a time to the start
+    for expected in states[..6].iter().rev() {
+        assert!(rewind.rewind(&mut chip8));
+        assert_eq!(&chip8.save_state(), expected);
+    }
+    assert!(!rewind.rewind(&mut chip8));
+    assert!(rewind.is_empty());
+    assert_eq!(&chip8.save_state(), &states[0]);
+}
+
+#[test]
+fn oldest_snapshots_are_dropped_at_capacity() {
+    let mut chip8 = machine();
+    let mut rewind = Rewind::new(3);
+    let mut states = vec![chip8.save_state()];
+    rewind.push(&chip8);
+    for _ in 0..8 {
+        chip8.run_frame(5);
+        states.push(chip8.save_state());
+        rewind.push(&chip8);
+    }
+    assert_eq!(rewind.len(), 3);
+
+    for _ in 0..3 {
+        assert!(rewind.rewind(&mut chip8));
+    }
+    // Three frames back from the newest is as far as it goes
+    assert_eq!(&chip8.save_state(), &states[5]);
+    assert!(!rewind.rewind(&mut chip8));
+
+    // Running on from there records a new history
+    chip8.run_frame(5);
+    rewind.push(&chip8);
+    assert_eq!(rewind.len(), 1);
+    assert!(rewind.rewind(&mut chip8));
+    assert_eq!(&chip8.save_state(), &states[5]);
+}
@@ -0,0 +1,89 @@
+// ROM database: reading chip-8-database style JSON and the built-in entries
+
+use std::fs;
+
+use chip8::assembler::assemble;
+use chip8::romdb::RomDatabase;
+use chip8::{MemoryIncrement, Quirks};
+
+const PROGRAMS: &str = r##"
+[
+    {
+        "title": "Café \"Game\"",
+        "authors": ["A. Author", "B. Author"],
+        "release": "2024",
+        "roms": {
+            "A9993E364706816ABA3E25717850C26C9CD0D89D": {
+                "file": "abc.ch8",
+                "platforms": ["originalChip8", "superchip"],
+                "quirkyPlatforms": {
+                    "originalChip8": { "vblank": false, "memoryLeaveIUnchanged": true, "wrap": true }
+                },
+                "tickrate": 30,
+                "colors": { "pixels": ["#102030", "#ffeedd"], "buzzer": "#ff0000" },
+                "keys": { "left": 4, "right": 6, "bad": 99 }
+            }
+        }
+    },
+    {
+        "title": "MegaChip only",
+        "roms": { "da39a3ee5e6b4b0d3255bfef95601890afd80709": { "platforms": ["megachip8"] } }
+    }
+]
+"##;
+
+#[test]
+fn reads_chip8_database_entries() {
+    let database = RomDatabase::parse(PROGRAMS).unwrap();
+    assert_eq!(database.len(), 2);
+
+    let info = database.lookup(b"abc").unwrap();
+    assert_eq!(info.title, "Café \"Game\"");
+    assert_eq!(info.authors, ["A. Author", "B. Author"]);
+    assert_eq!(info.platform.as_deref(), Some("originalChip8"));
+    assert_eq!(info.tickrate, Some(30));
+    assert_eq!(info.colors, [(0x10, 0x20, 0x30), (0xff, 0xee, 0xdd)]);
+    assert_eq!(info.keys, [("left".to_string(), 4), ("right".to_string(), 6)]);
+
+    // The VIP with the ROM's quirky overrides
+    let quirks = info.quirks.unwrap();
+    assert_eq!(
+        quirks,
+        Quirks { display_wait: false, memory_increment: MemoryIncrement::None, clip_sprites: false, ..Quirks::COSMAC_VIP }
+    );
+
+    // Known, but not runnable here
+    assert_eq!(database.lookup(b"").unwrap().quirks, None);
+    assert!(database.lookup(b"abcd").is_none());
+}
+
+#[test]
+fn rejects_malformed_json() {
+    assert!(RomDatabase::parse("[{\"title\": \"x\",}]").unwrap_err().starts_with("line 1:"));
+    assert!(RomDatabase::parse("[{\"roms\": {}}]").is_err());
+}
+
+#[test]
+fn builtin_knows_the_test_roms() {
+    // Guards the hashes in data/programs.json against assembler changes
+    let database = RomDatabase::builtin();
+    for (file, title) in [("alu.8o", "ALU self-test"), ("schip.8o", "SUPER-CHIP display test"), ("xochip.8o", "XO-CHIP bitplane test")] {
+        let rom = assemble(&fs::read_to_string(format!("tests/roms/{}", file)).unwrap()).unwrap();
+        let info = database.lookup(&rom).unwrap_or_else(|| panic!("{} is not in data/programs.json", file));
+        assert_eq!(info.title, title);
+    }
+}
+
+#[test]
+fn builtin_knows_classic_roms() {
+    let database = RomDatabase::builtin();
+    for (file, title) in [("ibm-logo.ch8", "IBM Logo"), ("maze.ch8", "Maze")] {
+        let rom = fs::read(format!("tests/roms/{}", file)).unwrap();
+        let info = database.lookup(&rom).unwrap_or_else(|| panic!("{} is not in data/programs.json", file));
+        assert_eq!(info.title, title);
+        // Written for the VIP, so they get its quirks
+        assert_eq!(info.quirks, Some(Quirks::COSMAC_VIP));
+    }
+    let maze = database.lookup(&fs::read("tests/roms/maze.ch8").unwrap()).unwrap();
+    assert_eq!(maze.authors, ["David Winter"]);
+}